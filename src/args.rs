@@ -1,5 +1,6 @@
+use std::ops::Range;
 use std::path::PathBuf;
-use clap::{Parser, Args, Subcommand};
+use clap::{Parser, Args, Subcommand, ValueEnum};
 
 #[derive(Parser,Debug)]
 #[command(name = "Png Encoder")]
@@ -9,6 +10,34 @@ use clap::{Parser, Args, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// output format of print, decode, validate, scan, diff, capacity, dump and types
+    #[arg(long, global = true, value_enum, default_value_t=Format::Text)]
+    pub format: Format,
+
+    #[command(flatten)]
+    pub batch: BatchArgs,
+}
+
+/// how commands that take several files find and process them
+#[derive(Args,Debug,Clone,Default)]
+pub struct BatchArgs {
+    /// also process png files in subdirectories of directory arguments
+    #[arg(long, global = true)]
+    pub recursive: bool,
+
+    /// number of files to process at the same time, defaults to the number of CPUs
+    #[arg(long, short, global = true)]
+    pub jobs: Option<usize>,
+}
+
+/// how inspection commands write their results
+#[derive(ValueEnum,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Format {
+    /// human readable text
+    Text,
+    /// a JSON document on stdout
+    Json,
 }
 
 #[derive(Subcommand,Debug)]
@@ -20,61 +49,502 @@ pub enum Commands {
     /// remove the encoded message from the png file
     Remove(RemoveArgs),
     /// print all the chunks in a png file
-    Print(PrintArgs)
+    Print(PrintArgs),
+    /// check that the png file is well formed
+    Validate(ValidateArgs),
+    /// append a zip archive to a png so the file is both an image and an archive
+    Polyglot(PolyglotArgs),
+    /// pull a zip archive back out of a polyglot png
+    ExtractZip(ExtractZipArgs),
+    /// look for signs of hidden data and give each file a risk score
+    Scan(ScanArgs),
+    /// report how much payload each embedding method can hide in the png file
+    Capacity(CapacityArgs),
+    /// remove metadata chunks that are not needed to display the image
+    Strip(StripArgs),
+    /// show a hex dump of the fields of a chunk
+    Dump(DumpArgs),
+    /// write the data of a chunk to a file
+    Extract(ExtractArgs),
+    /// add a chunk with data read from a file
+    Insert(InsertArgs),
+    /// put the chunks in the order the spec recommends, or move a single chunk
+    Reorder(ReorderArgs),
+    /// recompute the CRC of every chunk and fix the ones that are wrong
+    RepairCrc(RepairCrcArgs),
+    /// show which chunks differ between two png files
+    Diff(DiffArgs),
+    /// work with patches made by `diff --emit-patch`
+    Patch(PatchArgs),
+    /// make a new png to carry a message when there is no cover image
+    Generate(GenerateArgs),
+    /// write every chunk of a png as JSON or YAML, for review and editing
+    DumpJson(DumpJsonArgs),
+    /// rebuild a png from the output of `dump-json`
+    BuildJson(BuildJsonArgs),
+    /// list the chunk types the tool knows, or look up the given ones
+    Types(TypesArgs),
+
+}
+
+/// which ancillary chunks `strip` keeps when no allow or deny list is given
+#[derive(ValueEnum,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Keep {
+    /// only critical chunks and tRNS
+    Critical,
+    /// critical chunks and tRNS plus gAMA, cHRM, sRGB and iCCP
+    Color,
+}
+
+/// what a generated image looks like
+#[derive(ValueEnum,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Style {
+    /// fine grain around a single color
+    Noise,
+    /// a smooth blend between two colors
+    Gradient,
+    /// sky over textured ground, like a landscape photo
+    PhotoLike,
+}
+
+/// where the secret message is stored in the png file
+#[derive(ValueEnum,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Method {
+    /// a custom chunk inserted before IEND
+    Chunk,
+    /// raw bytes appended after IEND
+    Trailer,
+}
 
+/// where commands that change a png write the result
+#[derive(Args,Debug,Clone,Default)]
+pub struct OutputArgs {
+    /// write the result here instead of replacing the input file, `-` for stdout
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// when replacing the input file, keep the original with this suffix, e.g. `.bak`
+    #[arg(long, conflicts_with = "output")]
+    pub backup: Option<String>,
 }
 
 #[derive(Args,Debug)]
 pub struct EncodeArgs {
-    /// path to image file
-    #[arg(long, short)]
-    pub path: PathBuf,
+    /// image files, globs or directories; `-` reads from stdin and writes the result to stdout
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
 
     /// chunk_type 
     #[arg(long, short, default_value_t=String::from("ruSt"))]
     pub chunk_type: String,
 
-    #[arg(long, short)]
-    pub message: String
+    /// hide the message in a chunk of a random private type instead, printed to stderr
+    #[arg(long, conflicts_with = "chunk_type")]
+    pub random_type: bool,
+
+    /// the secret message
+    #[arg(long, short, required_unless_present = "message_file", conflicts_with = "message_file")]
+    pub message: Option<String>,
+
+    /// read the secret from this file instead, `-` reads it from stdin
+    #[arg(long)]
+    pub message_file: Option<PathBuf>,
+
+    /// where to hide the message
+    #[arg(long, value_enum, default_value_t=Method::Chunk)]
+    pub method: Method,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
 }
 
 
 #[derive(Args,Debug)]
 pub struct DecodeArgs {
-/// path to image file
-    #[arg(long, short)]
-    pub path: PathBuf,
+/// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
 
     /// chunk_type 
     #[arg(long, short, default_value_t=String::from("ruSt"))]
     pub chunk_type: String,
+
+    /// where to look for the message
+    #[arg(long, value_enum, default_value_t=Method::Chunk)]
+    pub method: Method,
+
+    /// write the message bytes to stdout exactly as stored, without quoting
+    #[arg(long)]
+    pub raw: bool,
 }
 
 
 #[derive(Args,Debug)]
 pub struct RemoveArgs {
-/// delete the encoded message from the png file
-    #[arg(long, short)]
-    pub path: PathBuf,
+/// image files, globs or directories to delete the encoded message from
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
 
     /// chunk_type 
     #[arg(long, short, default_value_t=String::from("ruSt"))]
     pub chunk_type: String,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
 }
 
 
 #[derive(Args,Debug)]
 pub struct PrintArgs {
-/// path to image file
+/// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// only print chunks of this type
+    #[arg(long = "type", short, visible_alias = "chunk-type")]
+    pub chunk_type: Option<String>,
+}
+
+
+#[derive(Args,Debug)]
+pub struct ValidateArgs {
+/// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+}
+
+
+#[derive(Args,Debug)]
+pub struct PolyglotArgs {
+    /// zip archive to append
+    #[arg(long, short)]
+    pub zip: PathBuf,
+
+    /// png images to use as carriers
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// where to write the combined file, a directory when there are several inputs
+    pub output: PathBuf,
+}
+
+
+#[derive(Args,Debug)]
+pub struct ExtractZipArgs {
+    /// polyglot png files
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// where to write the extracted zip archive, a directory when there are several inputs
+    pub output: PathBuf,
+}
+
+
+#[derive(Args,Debug)]
+pub struct ScanArgs {
+    /// png files, globs or directories to scan
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+
+#[derive(Args,Debug)]
+pub struct CapacityArgs {
+    /// image files, globs or directories
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+
+#[derive(Args,Debug)]
+pub struct StripArgs {
+    /// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// which chunks to keep
+    #[arg(long, value_enum, default_value_t=Keep::Critical, conflicts_with_all = ["allow", "deny"])]
+    pub keep: Keep,
+
+    /// keep critical chunks, tRNS and these types, e.g. `--allow pHYs,tEXt`
+    #[arg(long, value_delimiter = ',', conflicts_with = "deny")]
+    pub allow: Vec<String>,
+
+    /// remove these types and unknown chunks that are not safe to copy, e.g. `--deny tEXt,tIME`
+    #[arg(long, value_delimiter = ',')]
+    pub deny: Vec<String>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+
+#[derive(Args,Debug)]
+pub struct DumpArgs {
+    /// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// position of the chunk in the file, starting at 0
+    #[arg(long, short, required_unless_present = "chunk_type", conflicts_with = "chunk_type")]
+    pub index: Option<usize>,
+
+    /// dump every chunk of this type
+    #[arg(long = "type", short = 'c')]
+    pub chunk_type: Option<String>,
+
+    /// only dump this byte range of the data field, e.g. `0..256`, `0x100..` or `..64`
+    #[arg(long, short, value_parser = parse_range)]
+    pub range: Option<Range<usize>>,
+}
+
+
+#[derive(Args,Debug)]
+pub struct ExtractArgs {
+    /// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// type of the chunk to extract, the first one in the file unless `--all` is given
+    #[arg(long = "type", short = 'c')]
+    pub chunk_type: String,
+
+    /// concatenate the data of every chunk of this type, e.g. all IDAT chunks
+    #[arg(long)]
+    pub all: bool,
+
+    /// file to write the chunk data to, `-` for stdout, a directory when there are several images
+    #[arg(long, short)]
+    pub output: PathBuf,
+}
+
+
+#[derive(Args,Debug)]
+#[command(group = clap::ArgGroup::new("position").multiple(false))]
+pub struct InsertArgs {
+    /// image files, globs or directories; `-` reads from stdin and writes the result to stdout
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// type of the new chunk
+    #[arg(long = "type", short = 'c')]
+    pub chunk_type: String,
+
+    /// file holding the data of the new chunk, `-` reads it from stdin
+    #[arg(long)]
+    pub data_file: PathBuf,
+
+    /// insert in front of the first chunk of this type, the default is `--before IEND`
+    #[arg(long, group = "position")]
+    pub before: Option<String>,
+
+    /// insert right after the first chunk of this type
+    #[arg(long, group = "position")]
+    pub after: Option<String>,
+
+    /// insert at this position, starting at 0
+    #[arg(long, group = "position")]
+    pub index: Option<usize>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+
+#[derive(Args,Debug)]
+pub struct ReorderArgs {
+    /// image files, globs or directories; `-` reads from stdin and writes the result to stdout
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// index of a chunk to move instead of reordering the whole file
+    #[arg(long = "move", requires = "to")]
+    pub from: Option<usize>,
+
+    /// index the moved chunk ends up at
+    #[arg(long, requires = "from")]
+    pub to: Option<usize>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+
+#[derive(Args,Debug)]
+pub struct RepairCrcArgs {
+    /// image files, globs or directories; `-` reads from stdin and writes the result to stdout
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// only report bad CRCs, failing if there are any, without writing a file
+    #[arg(long, conflicts_with_all = ["output", "backup"])]
+    pub check_only: bool,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+
+#[derive(Args,Debug)]
+pub struct DiffArgs {
+    /// the original png file
+    pub old: PathBuf,
+
+    /// the changed png file
+    pub new: PathBuf,
+
+    /// also decode both images and check whether they look the same
+    #[arg(long)]
+    pub pixels: bool,
+
+    /// write a patch that turns the old file into the new one here, `-` for stdout
+    #[arg(long)]
+    pub emit_patch: Option<PathBuf>,
+}
+
+
+#[derive(Args,Debug)]
+pub struct PatchArgs {
+    #[command(subcommand)]
+    pub command: PatchCommands,
+}
+
+#[derive(Subcommand,Debug)]
+pub enum PatchCommands {
+    /// apply a patch to the file it was made from
+    Apply(PatchApplyArgs),
+}
+
+#[derive(Args,Debug)]
+pub struct PatchApplyArgs {
+    /// the png file the patch was made from, `-` reads it from stdin and writes the result to stdout
     #[arg(long, short)]
     pub path: PathBuf,
 
+    /// the patch file
+    #[arg(long)]
+    pub patch: PathBuf,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+/// A byte offset, in decimal or in hex with a `0x` prefix as `dump` prints them
+fn parse_offset(offset: &str) -> Option<usize> {
+    match offset.strip_prefix("0x").or_else(|| offset.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => offset.parse().ok(),
+    }
+}
+
+/// A range like `0..256`, `0x100..0x200`, `16..` or `..64`
+fn parse_range(range: &str) -> Result<Range<usize>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("`{}` is not a range like 0..256", range))?;
+    let start: usize = if start.is_empty() { 0 } else { parse_offset(start).ok_or_else(|| format!("bad start `{}`", start))? };
+    let end: usize = if end.is_empty() { usize::MAX } else { parse_offset(end).ok_or_else(|| format!("bad end `{}`", end))? };
+    if start > end {
+        return Err(format!("range `{}` ends before it starts", range));
+    }
+    Ok(start..end)
+}
+
+
+#[derive(Args,Debug)]
+pub struct GenerateArgs {
+    /// width of the image in pixels
+    #[arg(long)]
+    pub width: u32,
+
+    /// height of the image in pixels
+    #[arg(long)]
+    pub height: u32,
+
+    /// what the image looks like
+    #[arg(long, value_enum, default_value_t=Style::PhotoLike)]
+    pub style: Style,
+
+    /// seed for the random generator, to make the same image again
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// file to write the image to, `-` for stdout
+    #[arg(long, short)]
+    pub output: PathBuf,
+
+    /// hide this message in the new image
+    #[arg(long, short, conflicts_with = "message_file")]
+    pub message: Option<String>,
+
+    /// hide the contents of this file instead, `-` reads it from stdin
+    #[arg(long)]
+    pub message_file: Option<PathBuf>,
+
     /// chunk_type 
     #[arg(long, short, default_value_t=String::from("ruSt"))]
     pub chunk_type: String,
+
+    /// hide the message in a chunk of a random private type instead, printed to stderr
+    #[arg(long, conflicts_with = "chunk_type")]
+    pub random_type: bool,
+
+    /// where to hide the message
+    #[arg(long, value_enum, default_value_t=Method::Chunk)]
+    pub method: Method,
 }
 
 
+#[derive(Args,Debug)]
+pub struct DumpJsonArgs {
+    /// image files, globs or directories
+    #[arg(long, short, num_args = 1.., required = true)]
+    pub path: Vec<PathBuf>,
+
+    /// write YAML instead of JSON
+    #[arg(long)]
+    pub yaml: bool,
+}
 
 
+#[derive(Args,Debug)]
+pub struct BuildJsonArgs {
+    /// JSON or YAML written by `dump-json`, `-` reads it from stdin
+    #[arg(long, short)]
+    pub input: PathBuf,
 
+    /// file to write the png to, `-` for stdout
+    #[arg(long, short)]
+    pub output: PathBuf,
+}
+
+
+#[derive(Args,Debug)]
+pub struct TypesArgs {
+    /// chunk types to look up, e.g. `iCCP ruSt`; lists every known type when empty
+    pub names: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("0..256"), Ok(0..256));
+        assert_eq!(parse_range("16.."), Ok(16..usize::MAX));
+        assert_eq!(parse_range("..64"), Ok(0..64));
+        assert_eq!(parse_range(".."), Ok(0..usize::MAX));
+        assert_eq!(parse_range("0x10..0X1f"), Ok(16..31));
+        assert_eq!(parse_range("8..8"), Ok(8..8));
+    }
+
+    #[test]
+    fn test_parse_range_rejects_bad_ranges() {
+        assert!(parse_range("256").is_err());
+        assert!(parse_range("a..b").is_err());
+        assert!(parse_range("0x..4").is_err());
+        assert!(parse_range("-1..4").is_err());
+        assert_eq!(parse_range("10..2"), Err("range `10..2` ends before it starts".to_string()));
+    }
+}
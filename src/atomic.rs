@@ -0,0 +1,95 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Temporary file next to `path`, so that renaming it over `path` never crosses filesystems
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.pngme-{}.tmp", name, std::process::id()))
+}
+
+/// Writes `bytes` to `path` so that readers see either the old or the new contents, never
+/// a partial file: the data goes to a temporary file in the same directory, is synced to
+/// disk and then renamed over `path`. If `backup` is given and `path` exists, the old
+/// contents are first copied to `path` with that suffix appended.
+pub fn write_atomic(path: &Path, bytes: &[u8], backup: Option<&str>) -> io::Result<()> {
+    if let Some(suffix) = backup {
+        if path.exists() {
+            let mut backup_path = path.as_os_str().to_owned();
+            backup_path.push(suffix);
+            fs::copy(path, &backup_path)?;
+        }
+    }
+
+    let temp = temp_path(path);
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temp, metadata.permissions())?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    // make the rename itself durable; not every platform can open a directory, so this is best effort
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-atomic-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let dir = scratch_dir("replace");
+        let path = dir.join("image.png");
+        fs::write(&path, b"old").unwrap();
+
+        write_atomic(&path, b"new", None).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let dir = scratch_dir("backup");
+        let path = dir.join("image.png");
+        fs::write(&path, b"old").unwrap();
+
+        write_atomic(&path, b"new", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read(dir.join("image.png.bak")).unwrap(), b"old");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_new_file() {
+        let dir = scratch_dir("new");
+        let path = dir.join("out.png");
+
+        write_atomic(&path, b"data", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        assert!(!dir.join("out.png.bak").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
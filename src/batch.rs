@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::args::{BatchArgs, Format};
+use crate::output;
+use pngme::png::Png;
+use pngme::{Error, Result};
+
+/// The files found for a list of command line paths, plus those that were passed over and
+/// the patterns that could not be used at all
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Expanded {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<(PathBuf, String)>,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// True when `patterns` can name more than one file, so output is grouped per file
+pub fn is_batch(patterns: &[PathBuf]) -> bool {
+    patterns.len() > 1 || patterns.iter().any(|p| p.is_dir() || (is_glob(p) && !p.exists()))
+}
+
+/// True when the file starts with the PNG signature
+fn has_png_signature(path: &Path) -> io::Result<bool> {
+    let mut signature = [0; 8];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut signature) {
+        Ok(()) => Ok(signature == Png::STANDARD_HEADER),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+impl Expanded {
+    /// Adds a file found by listing a directory or matching a glob; unlike files named
+    /// explicitly these are skipped rather than failed when they are not PNGs
+    fn discovered(&mut self, path: PathBuf, recursive: bool) {
+        if path.is_dir() {
+            return self.directory(&path, recursive);
+        }
+        match has_png_signature(&path) {
+            Ok(true) => self.files.push(path),
+            Ok(false) => self.skipped.push((path, "not a png file".to_string())),
+            Err(err) => self.skipped.push((path, err.to_string())),
+        }
+    }
+
+    fn directory(&mut self, dir: &Path, recursive: bool) {
+        let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+            Err(err) => return self.skipped.push((dir.to_path_buf(), err.to_string())),
+        };
+        entries.sort();
+        for entry in entries {
+            if !entry.is_dir() {
+                self.discovered(entry, recursive);
+            } else if recursive {
+                self.directory(&entry, recursive);
+            }
+        }
+    }
+}
+
+/// Turns command line paths into the files to process. Directories contribute the PNG
+/// files directly inside them, or every PNG below them with `recursive`; glob patterns are
+/// expanded here so they also work where the shell does not expand them.
+pub fn expand(patterns: &[PathBuf], recursive: bool) -> Expanded {
+    let mut expanded = Expanded::default();
+    for pattern in patterns {
+        if pattern.is_dir() {
+            expanded.directory(pattern, recursive);
+        } else if is_stdio(pattern) || pattern.exists() || !is_glob(pattern) {
+            // missing files are kept so that the command reports them as failed
+            expanded.files.push(pattern.clone());
+        } else {
+            let matches = match glob::glob(&pattern.to_string_lossy()) {
+                Ok(matches) => matches,
+                Err(err) => {
+                    expanded.failed.push((pattern.clone(), format!("invalid glob pattern: {}", err)));
+                    continue;
+                }
+            };
+            let before = expanded.files.len() + expanded.skipped.len();
+            for entry in matches {
+                match entry {
+                    Ok(path) => expanded.discovered(path, recursive),
+                    Err(err) => expanded.skipped.push((err.path().to_path_buf(), err.error().to_string())),
+                }
+            }
+            if expanded.files.len() + expanded.skipped.len() == before {
+                expanded.failed.push((pattern.clone(), "no files match".to_string()));
+            }
+        }
+    }
+    expanded
+}
+
+/// What one file produced: everything the command wrote, and whether it succeeded
+type Outcome = (Vec<u8>, std::result::Result<(), String>);
+
+/// Runs `f` on every file with `jobs` worker threads, handing the outcomes to `done`
+/// in the order of `files` no matter which worker finishes first
+fn process<F>(files: &[PathBuf], jobs: usize, f: F, mut done: impl FnMut(usize, Outcome))
+where
+    F: Fn(&Path, &mut Vec<u8>) -> Result<()> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            let sender = sender.clone();
+            let (next, f) = (&next, &f);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = files.get(index) else { break };
+                let mut out = vec![];
+                // errors are not Send, so only their message leaves the worker
+                let result = f(path, &mut out).map_err(|err| err.to_string());
+                if sender.send((index, (out, result))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (index, outcome) in receiver {
+            pending.insert(index, outcome);
+            while let Some(outcome) = pending.remove(&expected) {
+                done(expected, outcome);
+                expected += 1;
+            }
+        }
+    });
+}
+
+/// The JSON element for one file, the same shape whatever happened: its path, the error
+/// (or `null`), the document the command printed if it printed JSON, and any other text
+fn json_entry(path: &Path, (out, result): &Outcome) -> Value {
+    let document = serde_json::from_slice::<Value>(out).ok();
+    let text = (document.is_none() && !out.is_empty()).then(|| String::from_utf8_lossy(out).to_string());
+    json!({
+        "path": path.display().to_string(),
+        "error": result.as_ref().err(),
+        "result": document,
+        "output": text,
+    })
+}
+
+/// Runs a command on every file named by `patterns`. A single plain file prints as before;
+/// anything else is a batch, where failures are collected instead of stopping the run,
+/// output is grouped per file and a summary of succeeded, failed and skipped files goes to
+/// stderr. With `--format json` the output is always one array with an element per file.
+/// Stdin (`-`) only works on its own, since an image read from it is written back to stdout.
+pub fn run<F>(patterns: &[PathBuf], batch: &BatchArgs, format: Format, f: F) -> Result<()>
+where
+    F: Fn(&Path, &mut Vec<u8>) -> Result<()> + Sync,
+{
+    let single = !is_batch(patterns);
+    if !single && patterns.iter().any(|p| is_stdio(p)) {
+        return Err(Error::from("`-` reads a single image from stdin and cannot be combined with other inputs"));
+    }
+    if single && format == Format::Text {
+        let mut out = vec![];
+        let result = f(&patterns[0], &mut out);
+        io::stdout().write_all(&out)?;
+        return result;
+    }
+
+    let Expanded { files, skipped, failed: unusable } = expand(patterns, batch.recursive);
+    let jobs = batch
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+    let mut failed = vec![];
+    let mut documents = vec![];
+    let mut stdout = io::stdout().lock();
+    let mut write_error = None;
+    process(&files, jobs, f, |index, outcome| {
+        let path = &files[index];
+        if format == Format::Json {
+            documents.push(json_entry(path, &outcome));
+        } else if !outcome.0.is_empty() {
+            let written = writeln!(stdout, "==> {} <==", path.display()).and_then(|_| stdout.write_all(&outcome.0));
+            if let Err(err) = written {
+                write_error.get_or_insert(err);
+            }
+        }
+        if let Err(err) = outcome.1 {
+            failed.push((path.clone(), err));
+        }
+    });
+    if let Some(err) = write_error {
+        return Err(Error::from(format!("failed to write output: {}", err)));
+    }
+    if format == Format::Json {
+        for (pattern, reason) in &unusable {
+            documents.push(json_entry(pattern, &(vec![], Err(reason.clone()))));
+        }
+        let mut out = vec![];
+        output::write_json(&mut out, &documents)?;
+        stdout.write_all(&out)?;
+    }
+    if single {
+        // a single file reports its own error, as in text mode
+        return match failed.pop() {
+            Some((_, reason)) => Err(Error::from(reason)),
+            None => Ok(()),
+        };
+    }
+
+    let (succeeded, total) = (files.len() - failed.len(), files.len() + unusable.len());
+    failed.extend(unusable);
+    eprintln!("{} succeeded, {} failed, {} skipped", succeeded, failed.len(), skipped.len());
+    for (path, reason) in &failed {
+        eprintln!("  failed: {}: {}", path.display(), reason);
+    }
+    for (path, reason) in &skipped {
+        eprintln!("  skipped: {}: {}", path.display(), reason);
+    }
+
+    match failed.len() {
+        0 => Ok(()),
+        n => Err(Error::from(format!("{} of {} files or patterns failed", n, total))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-batch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.png"), DICE).unwrap();
+        fs::write(dir.join("b.png"), DICE).unwrap();
+        fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+        fs::write(dir.join("nested").join("c.png"), DICE).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_expand_directory() {
+        let dir = scratch_dir("directory");
+        let expanded = expand(std::slice::from_ref(&dir), false);
+
+        assert_eq!(expanded.files, vec![dir.join("a.png"), dir.join("b.png")]);
+        assert_eq!(expanded.skipped, vec![(dir.join("notes.txt"), "not a png file".to_string())]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_recursive() {
+        let dir = scratch_dir("recursive");
+        let expanded = expand(std::slice::from_ref(&dir), true);
+
+        assert_eq!(
+            expanded.files,
+            vec![dir.join("a.png"), dir.join("b.png"), dir.join("nested").join("c.png")]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = scratch_dir("glob");
+        let expanded = expand(&[dir.join("*.png"), dir.join("*.gif")], false);
+
+        assert_eq!(expanded.files, vec![dir.join("a.png"), dir.join("b.png")]);
+        assert!(expanded.skipped.is_empty());
+        assert_eq!(expanded.failed, vec![(dir.join("*.gif"), "no files match".to_string())]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_keeps_explicit_files() {
+        let expanded = expand(&[PathBuf::from("-"), PathBuf::from("missing.png")], false);
+        assert_eq!(expanded.files, vec![PathBuf::from("-"), PathBuf::from("missing.png")]);
+        assert!(expanded.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_is_batch() {
+        assert!(!is_batch(&[PathBuf::from("dice.png")]));
+        assert!(is_batch(&[PathBuf::from("dice.png"), PathBuf::from("dice.png")]));
+        assert!(is_batch(&[PathBuf::from("*.png")]));
+        assert!(is_batch(&[PathBuf::from("src")]));
+    }
+
+    #[test]
+    fn test_run_refuses_stdin_in_batch() {
+        let batch = BatchArgs { recursive: false, jobs: None };
+        let patterns = [PathBuf::from("-"), PathBuf::from("dice.png")];
+        let err = run(&patterns, &batch, Format::Text, |_, _| panic!("nothing should run")).unwrap_err();
+        assert!(err.to_string().contains("stdin"));
+    }
+
+    #[test]
+    fn test_process_keeps_input_order() {
+        let files: Vec<PathBuf> = (0..50).map(|i| PathBuf::from(i.to_string())).collect();
+        let mut seen = vec![];
+        process(
+            &files,
+            4,
+            |path, out| {
+                let n: u64 = path.to_string_lossy().parse().unwrap();
+                thread::sleep(std::time::Duration::from_micros((50 - n) * 20));
+                if n.is_multiple_of(10) {
+                    return Err(Error::from("multiple of ten"));
+                }
+                out.extend_from_slice(path.to_string_lossy().as_bytes());
+                Ok(())
+            },
+            |index, (out, result)| seen.push((index, out, result)),
+        );
+
+        assert_eq!(seen.len(), 50);
+        for (i, (index, out, result)) in seen.into_iter().enumerate() {
+            assert_eq!(index, i);
+            if i % 10 == 0 {
+                assert_eq!(result, Err("multiple of ten".to_string()));
+            } else {
+                assert_eq!(out, i.to_string().into_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_entry() {
+        let path = Path::new("a.png");
+        let printed = (b"{\"path\": \"a.png\", \"valid\": false}\n".to_vec(), Err("bad".to_string()));
+        assert_eq!(
+            json_entry(path, &printed),
+            json!({"path": "a.png", "error": "bad", "result": {"path": "a.png", "valid": false}, "output": null})
+        );
+
+        let failed = (vec![], Err("bad".to_string()));
+        assert_eq!(json_entry(path, &failed), json!({"path": "a.png", "error": "bad", "result": null, "output": null}));
+
+        let text = (b"saved".to_vec(), Ok(()));
+        assert_eq!(json_entry(path, &text), json!({"path": "a.png", "error": null, "result": null, "output": "saved"}));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::order;
+use crate::pixels::{self, Header, Image};
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Largest amount of compressed image data put in a single IDAT chunk
+const IDAT_SIZE: usize = 1 << 16;
+
+/// Most samples an image may have, enough for four channels of a square image as large as
+/// `generate::MAX_SIDE` allows; bigger images are refused rather than allocated
+pub const MAX_SAMPLES: usize = 1 << 30;
+
+/// Builds a valid PNG from scratch: an IHDR describing the image, the pixel data in IDAT
+/// chunks, any ancillary chunks in the order the spec requires, and IEND.
+///
+/// ```
+/// use pngme::builder::PngBuilder;
+///
+/// let png = PngBuilder::new(16, 8, 2, 8)
+///     .fill(&[255, 128, 0])
+///     .text("Title", "orange")
+///     .build()?;
+/// assert_eq!(png.chunks().first().unwrap().chunk_type().to_string(), "IHDR");
+/// # Ok::<(), pngme::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct PngBuilder {
+    header: Header,
+    samples: Option<Vec<u16>>,
+    fill: Vec<u16>,
+    palette: Option<Vec<[u8; 3]>>,
+    chunks: Vec<Result<Chunk>>,
+}
+
+impl PngBuilder {
+    /// Starts an image of the given size, color type and bit depth, filled with zero samples
+    /// until `samples` or `fill` say otherwise
+    pub fn new(width: u32, height: u32, color_type: u8, bit_depth: u8) -> PngBuilder {
+        PngBuilder {
+            header: Header { width, height, bit_depth, color_type, interlaced: false },
+            samples: None,
+            fill: vec![],
+            palette: None,
+            chunks: vec![],
+        }
+    }
+
+    /// Sets the pixels: samples in raster order, one per channel of every pixel, as
+    /// `pixels::decode` returns them
+    pub fn samples(mut self, samples: Vec<u16>) -> PngBuilder {
+        self.samples = Some(samples);
+        self
+    }
+
+    /// Gives every pixel the same value, one sample per channel
+    pub fn fill(mut self, pixel: &[u16]) -> PngBuilder {
+        self.samples = None;
+        self.fill = pixel.to_vec();
+        self
+    }
+
+    /// Sets the PLTE entries, which color type 3 requires
+    pub fn palette(mut self, palette: Vec<[u8; 3]>) -> PngBuilder {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Adds a tEXt chunk
+    pub fn text(mut self, keyword: &str, text: &str) -> PngBuilder {
+        let chunk = if keyword.is_empty() || keyword.len() > 79 || keyword.contains('\0') {
+            Err(Error::from(format!("`{}` is not a valid text keyword", keyword)))
+        } else {
+            new_chunk("tEXt", [keyword.as_bytes(), &[0], text.as_bytes()].concat())
+        };
+        self.chunks.push(chunk);
+        self
+    }
+
+    /// Adds a gAMA chunk for the given image gamma, such as 1 / 2.2
+    pub fn gamma(mut self, gamma: f64) -> PngBuilder {
+        let value = (gamma * 100000.0).round() as u32;
+        self.chunks.push(new_chunk("gAMA", value.to_be_bytes().to_vec()));
+        self
+    }
+
+    /// Adds a pHYs chunk; `per_meter` says the pixel counts are per meter rather than
+    /// only giving the aspect ratio
+    pub fn physical(mut self, x: u32, y: u32, per_meter: bool) -> PngBuilder {
+        let mut data = vec![];
+        data.extend(x.to_be_bytes());
+        data.extend(y.to_be_bytes());
+        data.push(per_meter as u8);
+        self.chunks.push(new_chunk("pHYs", data));
+        self
+    }
+
+    /// Adds any other chunk; it is moved to where the spec wants it when the PNG is built
+    pub fn chunk(mut self, chunk: Chunk) -> PngBuilder {
+        self.chunks.push(Ok(chunk));
+        self
+    }
+
+    /// Encodes the pixels and assembles the chunks, failing if the header, pixels, palette
+    /// or extra chunks do not make a valid image
+    pub fn build(self) -> Result<Png> {
+        let header = self.header;
+        let ihdr = header.to_chunk()?;
+        let count = (header.width as usize)
+            .checked_mul(header.height as usize)
+            .filter(|count| count.checked_mul(header.channels()).is_some_and(|total| total <= MAX_SAMPLES))
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "a {}x{} image has more than {} samples",
+                    header.width, header.height, MAX_SAMPLES
+                ))
+            })?;
+        let samples = match self.samples {
+            Some(samples) => samples,
+            None if self.fill.is_empty() => vec![0; count * header.channels()],
+            None if self.fill.len() == header.channels() => self.fill.repeat(count),
+            None => {
+                return Err(Error::from(format!(
+                    "fill has {} samples but color type {} pixels have {}",
+                    self.fill.len(),
+                    header.color_type,
+                    header.channels()
+                )))
+            }
+        };
+
+        let mut chunks = vec![ihdr];
+        match (&self.palette, header.color_type) {
+            (Some(palette), 2 | 3 | 6) => {
+                let limit = 256.min(1 << header.bit_depth);
+                if palette.is_empty() || palette.len() > limit {
+                    return Err(Error::from(format!("a palette needs 1 to {} entries, got {}", limit, palette.len())));
+                }
+                if header.color_type == 3 {
+                    if let Some(index) = samples.iter().find(|&&index| index as usize >= palette.len()) {
+                        return Err(Error::from(format!("palette index {} has no entry", index)));
+                    }
+                }
+                chunks.push(new_chunk("PLTE", palette.concat())?);
+            }
+            (None, 3) => return Err(Error::from("color type 3 needs a palette")),
+            (None, _) => {}
+            (Some(_), color_type) => {
+                return Err(Error::from(format!("color type {} cannot have a palette", color_type)))
+            }
+        }
+        for chunk in self.chunks {
+            chunks.push(chunk?);
+        }
+
+        let data = pixels::encode(&Image { header, samples })?;
+        for part in data.chunks(IDAT_SIZE) {
+            chunks.push(new_chunk("IDAT", part.to_vec())?);
+        }
+        chunks.push(Chunk::new(ChunkType::IEND, vec![]));
+
+        let mut png = Png::from_chunks(chunks);
+        order::normalize(&mut png);
+        let violations = order::violations(png.chunks());
+        if !violations.is_empty() {
+            return Err(Error::from(format!("the chunks do not make a valid png: {}", violations.join(", "))));
+        }
+        Ok(png)
+    }
+}
+
+fn new_chunk(chunk_type: &str, data: Vec<u8>) -> Result<Chunk> {
+    Ok(Chunk::new(chunk_type.parse::<ChunkType>()?, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_build_round_trip() {
+        let samples: Vec<u16> = (0..4 * 3 * 3).map(|i| i * 7).collect();
+        let png = PngBuilder::new(4, 3, 2, 8)
+            .samples(samples.clone())
+            .text("Comment", "made by pngme")
+            .physical(2835, 2835, true)
+            .gamma(1.0 / 2.2)
+            .build()
+            .unwrap();
+
+        assert_eq!(names(&png), vec!["IHDR", "tEXt", "gAMA", "pHYs", "IDAT", "IEND"]);
+        let parsed = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert_eq!(parsed.chunk_by_type(&"gAMA".parse().unwrap()).unwrap().data(), 45455u32.to_be_bytes());
+        assert_eq!(pixels::decode(&parsed).unwrap().samples, samples);
+    }
+
+    #[test]
+    fn test_build_fill_sixteen_bit() {
+        let png = PngBuilder::new(3, 2, 4, 16).fill(&[1000, 65535]).build().unwrap();
+        let image = pixels::decode(&png).unwrap();
+        assert_eq!(image.samples, [1000, 65535].repeat(6));
+    }
+
+    #[test]
+    fn test_build_palette() {
+        let png = PngBuilder::new(5, 2, 3, 2)
+            .palette(vec![[0, 0, 0], [255, 0, 0], [0, 0, 255]])
+            .samples(vec![0, 1, 2, 1, 0, 2, 2, 1, 1, 0])
+            .build()
+            .unwrap();
+
+        assert_eq!(names(&png), vec!["IHDR", "PLTE", "IDAT", "IEND"]);
+        let image = pixels::decode(&png).unwrap();
+        let rgba = pixels::rgba(&png, &image).unwrap();
+        assert_eq!(rgba[1], [65535, 0, 0, 65535]);
+        assert_eq!(rgba[2], [0, 0, 65535, 65535]);
+    }
+
+    #[test]
+    fn test_build_orders_extra_chunks() {
+        let srgb = Chunk::new("sRGB".parse().unwrap(), vec![0]);
+        let png = PngBuilder::new(1, 1, 3, 8)
+            .chunk(srgb)
+            .palette(vec![[1, 2, 3]])
+            .build()
+            .unwrap();
+        assert_eq!(names(&png), vec!["IHDR", "sRGB", "PLTE", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_images() {
+        assert!(PngBuilder::new(0, 1, 0, 8).build().is_err());
+        assert!(PngBuilder::new(1, 1, 2, 4).build().is_err());
+        assert!(PngBuilder::new(1, 1, 3, 8).build().is_err());
+        assert!(PngBuilder::new(1, 1, 0, 8).palette(vec![[0, 0, 0]]).build().is_err());
+        assert!(PngBuilder::new(2, 1, 3, 1).palette(vec![[0; 3]]).samples(vec![0, 1]).build().is_err());
+        assert!(PngBuilder::new(2, 2, 0, 8).samples(vec![0; 3]).build().is_err());
+        assert!(PngBuilder::new(1, 1, 0, 4).fill(&[16]).build().is_err());
+        assert!(PngBuilder::new(1, 1, 2, 8).fill(&[1]).build().is_err());
+        assert!(PngBuilder::new(1, 1, 0, 8).text("", "empty keyword").build().is_err());
+    }
+
+    #[test]
+    fn test_build_rejects_huge_images() {
+        let err = PngBuilder::new(u32::MAX, u32::MAX, 6, 16).fill(&[0, 0, 0, 0]).build().unwrap_err();
+        assert!(err.to_string().contains("samples"));
+        assert!(PngBuilder::new((1 << 16) + 1, 1 << 14, 0, 8).build().is_err());
+    }
+
+    #[test]
+    fn test_build_splits_large_idat() {
+        let mut state = 0x2545f491u32;
+        let samples: Vec<u16> = (0..256 * 256 * 3)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u16
+            })
+            .collect();
+        let png = PngBuilder::new(256, 256, 2, 8).samples(samples.clone()).build().unwrap();
+        assert!(png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == "IDAT").count() > 1);
+        assert_eq!(pixels::decode(&png).unwrap().samples, samples);
+    }
+}
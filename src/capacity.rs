@@ -0,0 +1,156 @@
+use std::fmt;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::pixels::Header;
+use crate::png::Png;
+use crate::Result;
+
+/// Keyword used when the payload is stored in a text chunk
+pub const TEXT_KEYWORD: &str = "Comment";
+
+/// How much payload a method can carry in a particular image. These are raw upper bounds:
+/// the tool hides messages without an envelope, encryption or erasure coding, so nothing
+/// is subtracted for them, and callers that add such layers must subtract their overhead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capacity {
+    Bytes(u64),
+    /// Only limited by file size, e.g. data appended after IEND
+    Unlimited,
+    /// The method cannot be used with this image, and why
+    NotApplicable(&'static str),
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capacity::Bytes(bytes) => write!(f, "{} bytes", bytes),
+            Capacity::Unlimited => write!(f, "unlimited"),
+            Capacity::NotApplicable(reason) => write!(f, "n/a ({})", reason),
+        }
+    }
+}
+
+/// Capacity of one embedding method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    pub method: String,
+    pub capacity: Capacity,
+}
+
+/// Bytes left for the payload of a single custom chunk
+fn chunk_capacity() -> Capacity {
+    Capacity::Bytes(Chunk::MAX_LENGTH as u64)
+}
+
+/// Text chunks may only hold printable Latin-1, so the payload is base64 encoded
+/// after the keyword and its null separator
+fn text_capacity() -> Capacity {
+    let text = Chunk::MAX_LENGTH as u64 - TEXT_KEYWORD.len() as u64 - 1;
+    Capacity::Bytes(text / 4 * 3)
+}
+
+/// Bytes that fit in the lowest `bits` bits of every color sample. Alpha is left
+/// alone since changing it in transparent areas is easy to spot.
+fn lsb_capacity(header: &Header, bits: u8) -> Capacity {
+    if header.color_type == 3 {
+        return Capacity::NotApplicable("palette indices are not color samples");
+    }
+    if bits > header.bit_depth {
+        return Capacity::NotApplicable("samples have fewer bits than that");
+    }
+    let color = if header.has_alpha() { header.channels() - 1 } else { header.channels() } as u64;
+    // a hostile header can claim more pixels than any file holds, so saturate rather than overflow
+    let bits = (header.width as u64)
+        .saturating_mul(header.height as u64)
+        .saturating_mul(color)
+        .saturating_mul(bits as u64);
+    Capacity::Bytes(bits / 8)
+}
+
+/// Reordering n palette entries can express any of n! permutations, i.e. log2(n!) bits
+fn palette_capacity(png: &Png) -> Capacity {
+    let Some(plte) = png.chunk_by_type(&ChunkType::PLTE) else {
+        return Capacity::NotApplicable("no PLTE chunk");
+    };
+    let entries = plte.data().len() / 3;
+    let bits: f64 = (2..=entries).map(|k| (k as f64).log2()).sum();
+    Capacity::Bytes(bits.floor() as u64 / 8)
+}
+
+/// Estimates the payload each embedding method can carry in `png`
+pub fn estimate(png: &Png) -> Result<Vec<Estimate>> {
+    let header = Header::from_png(png)?;
+    let mut estimates = vec![
+        Estimate { method: "chunk".to_string(), capacity: chunk_capacity() },
+        Estimate { method: "text".to_string(), capacity: text_capacity() },
+    ];
+    for bits in 1..=3 {
+        estimates.push(Estimate { method: format!("lsb-{}", bits), capacity: lsb_capacity(&header, bits) });
+    }
+    estimates.push(Estimate { method: "palette".to_string(), capacity: palette_capacity(png) });
+    estimates.push(Estimate { method: "trailer".to_string(), capacity: Capacity::Unlimited });
+    Ok(estimates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn capacity_of(estimates: &[Estimate], method: &str) -> Capacity {
+        estimates.iter().find(|e| e.method == method).unwrap().capacity
+    }
+
+    fn palette_png(entries: usize) -> Png {
+        #[rustfmt::skip]
+        let ihdr = vec![
+            0, 0, 0, 16,  // width
+            0, 0, 0, 16,  // height
+            8, 3, 0, 0, 0 // bit depth, color type, compression, filter, interlace
+        ];
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr),
+            Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![0; entries * 3]),
+        ])
+    }
+
+    #[test]
+    fn test_lsb_capacity_skips_alpha() {
+        let png = Png::try_from(DICE).unwrap();
+        let estimates = estimate(&png).unwrap();
+
+        assert_eq!(capacity_of(&estimates, "lsb-1"), Capacity::Bytes(420 * 315 * 3 / 8));
+        assert_eq!(capacity_of(&estimates, "lsb-3"), Capacity::Bytes(420 * 315 * 3 * 3 / 8));
+        assert!(matches!(capacity_of(&estimates, "palette"), Capacity::NotApplicable(_)));
+        assert_eq!(capacity_of(&estimates, "trailer"), Capacity::Unlimited);
+    }
+
+    #[test]
+    fn test_palette_capacity() {
+        let estimates = estimate(&palette_png(256)).unwrap();
+        // log2(256!) is just under 1684 bits
+        assert_eq!(capacity_of(&estimates, "palette"), Capacity::Bytes(210));
+        assert!(matches!(capacity_of(&estimates, "lsb-1"), Capacity::NotApplicable(_)));
+    }
+
+    #[test]
+    fn test_text_capacity_is_base64() {
+        assert_eq!(text_capacity(), Capacity::Bytes((Chunk::MAX_LENGTH as u64 - 8) / 4 * 3));
+    }
+
+    #[test]
+    fn test_lsb_capacity_saturates() {
+        let header = Header { width: 0x7fffffff, height: 0x7fffffff, bit_depth: 16, color_type: 6, interlaced: false };
+        assert_eq!(lsb_capacity(&header, 3), Capacity::Bytes(u64::MAX / 8));
+    }
+
+    #[test]
+    fn test_estimate_needs_ihdr() {
+        assert!(estimate(&Png::from_chunks(vec![])).is_err());
+    }
+}
@@ -1,108 +1,302 @@
 
-#![allow(unused_variables)]
-
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use crc::{Crc, CRC_32_ISO_HDLC};
+use serde::de::IgnoredAny;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::TryFrom;
 use std::fmt;
+use std::ops::{Deref, DerefMut};
 use crate::chunk_type::ChunkType;
+use crate::describe;
 
 use crate::{Error, Result};
-#[derive(Debug)]
+
+/// A single PNG chunk: a type, its data and the CRC covering both.
+/// Build one with `Chunk::new`, which computes the length and CRC, or parse one from bytes.
+/// Changing the data or type through the methods below keeps the CRC up to date.
+#[derive(Debug, Clone)]
 pub struct Chunk {
-    pub chunk_type: ChunkType,
-    pub data: Vec<u8>
+    chunk_type: ChunkType,
+    data: Vec<u8>,
+    // the CRC as stored, which only differs from the computed one for damaged chunks
+    crc: u32,
+}
+
+/// Mutable access to the data of a chunk, from `Chunk::data_mut`. The CRC is recomputed
+/// when the guard is dropped.
+///
+/// Panics on drop if the data has grown past `Chunk::MAX_LENGTH`, like `Chunk::set_data`;
+/// use `Chunk::try_set_data` for data of unknown size.
+pub struct DataMut<'a> {
+    chunk: &'a mut Chunk,
+}
+
+impl Deref for DataMut<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.chunk.data
+    }
+}
+
+impl DerefMut for DataMut<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.chunk.data
+    }
+}
+
+impl Drop for DataMut<'_> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            assert!(self.chunk.data.len() <= Chunk::MAX_LENGTH as usize, "chunk data is longer than the spec allows");
+        }
+        self.chunk.crc = self.chunk.crc();
+    }
 }
-#[allow(dead_code)]
+
+fn check_length(data: &[u8]) -> Result<()> {
+    if data.len() > Chunk::MAX_LENGTH as usize {
+        return Err(Error::from(format!(
+            "chunk data is {} bytes, longer than the {} the spec allows",
+            data.len(),
+            Chunk::MAX_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+    let mut digest = CRC.digest();
+    digest.update(&chunk_type.bytes());
+    digest.update(data);
+    digest.finalize()
+}
+
 impl Chunk {
+    /// The spec caps the length field of a chunk at 2^31 - 1
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+    /// Creates a chunk holding `data`, with the length and CRC filled in, failing if `data`
+    /// is longer than `MAX_LENGTH`. Use this for data that comes from users or files.
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        check_length(&data)?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    /// Creates a chunk holding `data`, with the length and CRC filled in. A convenience for
+    /// data that is known to fit, such as a header built in code; see `try_new`.
+    ///
+    /// Panics if `data` is longer than `MAX_LENGTH`.
     pub fn new(chunk_type:ChunkType, data:Vec<u8>) -> Chunk {
-        let data_length = data.len() as u32;
-        let mut data: Vec<u8> = data_length
-            .to_be_bytes()
-            .iter()
-            .chain(chunk_type.bytes().iter())
-            .chain(data.iter())
-            .copied()
-            .collect();
-        const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        let buffer = &data[4..(data_length as usize + 8)];
-        let crc = CRC.checksum(buffer);
-        data.extend(crc.to_be_bytes().iter());
-        let chunk = Chunk {
-             data,
-            chunk_type
-        };
-        chunk
+        assert!(data.len() <= Chunk::MAX_LENGTH as usize, "chunk data is longer than the spec allows");
+        let crc = checksum(&chunk_type, &data);
+        Chunk {
+            chunk_type,
+            data,
+            crc,
+        }
     }
 
+    /// The CRC computed from the type and data
     pub fn crc(&self) -> u32 {
-        let bytes = &self.data[4..(self.length() as usize) + 8];   
-        const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        CRC.checksum(bytes)
+        checksum(&self.chunk_type, &self.data)
+    }
+
+    /// The CRC as written in the chunk's bytes, which `crc` recomputes from the type and data
+    pub fn stored_crc(&self) -> u32 {
+        self.crc
     }
 
+    /// Length of the data in bytes
     pub fn length(&self) -> u32 {
-        let length =  u32::from_be_bytes(self.data[0..4].try_into().unwrap());
-        length
+        self.data.len() as u32
     }
 
     pub fn chunk_type(&self) -> &ChunkType {
         &self.chunk_type
     }
 
+    /// The data, without the length, type and CRC fields
     pub fn data(&self) -> &[u8] {
-        &self.data[8..(self.length() as usize + 8)]
+        &self.data
+    }
+
+    /// Replaces the data and recomputes the CRC, failing and leaving the chunk unchanged if
+    /// `data` is longer than `MAX_LENGTH`
+    pub fn try_set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        check_length(&data)?;
+        self.set_data(data);
+        Ok(())
+    }
+
+    /// Replaces the data and recomputes the CRC; see `try_set_data`.
+    ///
+    /// Panics if `data` is longer than `MAX_LENGTH`.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        assert!(data.len() <= Chunk::MAX_LENGTH as usize, "chunk data is longer than the spec allows");
+        self.data = data;
+        self.crc = self.crc();
+    }
+
+    /// Gives mutable access to the data in place; the CRC is recomputed when the guard is
+    /// dropped
+    pub fn data_mut(&mut self) -> DataMut<'_> {
+        DataMut { chunk: self }
     }
 
+    /// The same data under another type, with the CRC recomputed
+    pub fn with_type(self, chunk_type: ChunkType) -> Chunk {
+        Chunk::new(chunk_type, self.data)
+    }
+
+    /// The chunk as it is written in a file, including the length, type and CRC fields
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.data.to_vec()
+        let mut bytes = Vec::with_capacity(self.data.len() + 12);
+        bytes.extend(self.length().to_be_bytes());
+        bytes.extend(self.chunk_type.bytes());
+        bytes.extend(&self.data);
+        bytes.extend(self.crc.to_be_bytes());
+        bytes
+    }
+
+    /// Parses a chunk like `try_from` but keeps the CRC stored in `bytes` even when it is
+    /// wrong, so damaged files can still be read and repaired
+    pub fn parse_unchecked(bytes: &[u8]) -> Result<Chunk> {
+        if bytes.len() < 12 {
+            return Err(Error::from("invalid length of bytes"));
+        }
+        let length = u32::from_be_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        let mut chunk_type_slice = [0; 4];
+        chunk_type_slice.copy_from_slice(&bytes[4..8]);
+        let chunk_type = ChunkType::try_from(chunk_type_slice)
+            .map_err(|_| Error::from("chunk type is invalid"))?;
+        if length + 12 != bytes.len() {
+            return Err(Error::from("Invalid length of data"));
+        }
+        if length > Chunk::MAX_LENGTH as usize {
+            return Err(Error::from("chunk length is larger than the spec allows"));
+        }
+        Ok(Chunk {
+            chunk_type,
+            data: bytes[8..8 + length].to_vec(),
+            crc: u32::from_be_bytes(bytes[8 + length..].try_into().unwrap()),
+        })
+    }
+
+    /// True when the stored CRC matches the one computed from the type and data
+    pub fn crc_matches(&self) -> bool {
+        self.stored_crc() == self.crc()
     }
 
+    /// The data as UTF-8 text, failing if it is not valid UTF-8
     pub fn data_as_string(&self) -> Result<String> {
-        let data_as_string = std::str::from_utf8(&self.data()).unwrap();
+        let data_as_string = std::str::from_utf8(self.data())
+            .map_err(|err| Error::from(format!("chunk data is not valid UTF-8: {}", err)))?;
         Ok(data_as_string.to_string())
     }
 }
 
 impl fmt::Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f,"{}",self.data_as_string().unwrap())
+        write!(f,"{}",String::from_utf8_lossy(self.data()))
     }
 }
 
 
-impl TryFrom<&Vec<u8>> for Chunk {
-    type Error = Error;
-    fn try_from(bytes: &Vec<u8>) -> Result<Self>{
-        if bytes.len() < 8 {
-            return Err(Error::from("invalid length of bytes"));
-        }
-        let length_slice = &bytes[0..4];
-        let length = u32::from_be_bytes(length_slice.try_into().unwrap());
-        let mut chunk_type_slice = [0; 4];
-        for i in 4..8 {
-            chunk_type_slice[i-4] = bytes[i]; 
+/// Data that reads as text, so it is shown (and can be edited) as a string when serialized
+fn as_text(data: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(data).ok()?;
+    let plain = text.chars().all(|c| !c.is_control() || matches!(c, '\0' | '\t' | '\n' | '\r'));
+    plain.then_some(text)
+}
+
+/// Decoded fields written as a map that keeps their order
+struct Fields(Vec<(String, String)>);
+
+impl Serialize for Fields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
         }
-        let chunk_type = ChunkType::try_from(chunk_type_slice).unwrap();
-        if !chunk_type.is_valid() {
-            return Err(Error::from("chunk type is invalid"))
-        } 
-        if length + 8 >= bytes.len() as u32 {
-            return Err(Error::from("Invalid length of data"));
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct SerializedChunk<'a> {
+    #[serde(rename = "type")]
+    chunk_type: &'a ChunkType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<Fields>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crc: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeserializedChunk {
+    #[serde(rename = "type")]
+    chunk_type: ChunkType,
+    #[serde(default, rename = "fields")]
+    _fields: IgnoredAny,
+    text: Option<String>,
+    data: Option<String>,
+    crc: Option<u32>,
+}
+
+/// Serialized as the type, the decoded `fields` of well known chunk types (for reading
+/// only), the data as `text` when it reads as text or as base64 `data` otherwise, and the
+/// stored `crc` only when it is wrong, so a damaged chunk comes back exactly as it was
+impl Serialize for Chunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let text = as_text(self.data()).filter(|text| !text.is_empty());
+        SerializedChunk {
+            chunk_type: self.chunk_type(),
+            fields: describe::fields(self).map(Fields),
+            text,
+            data: (text.is_none() && !self.data().is_empty()).then(|| BASE64.encode(self.data())),
+            crc: (!self.crc_matches()).then(|| self.stored_crc()),
         }
+        .serialize(serializer)
+    }
+}
 
-        let mut data_slice: Vec<u8> = vec![];
-        for i in 8..(8+length) {
-            data_slice.push(bytes[i as usize]);
+impl<'de> Deserialize<'de> for Chunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        use serde::de::Error as _;
+        let chunk = DeserializedChunk::deserialize(deserializer)?;
+        let data = match (chunk.text, chunk.data) {
+            (Some(_), Some(_)) => return Err(D::Error::custom("a chunk has either `text` or `data`, not both")),
+            (Some(text), None) => text.into_bytes(),
+            (None, Some(data)) => BASE64
+                .decode(data)
+                .map_err(|err| D::Error::custom(format!("`{}` chunk data is not valid base64: {}", chunk.chunk_type, err)))?,
+            (None, None) => vec![],
+        };
+        let mut parsed = Chunk::try_new(chunk.chunk_type, data).map_err(|err| D::Error::custom(err.to_string()))?;
+        if let Some(crc) = chunk.crc {
+            parsed.crc = crc;
         }
-        let final_chunk = Chunk::new(chunk_type, data_slice);
-        let crc_slice = &bytes[(length + 8) as usize..];
-        let crc = u32::from_be_bytes(crc_slice.try_into().unwrap());
-        // println!("thier data {:?}", final_chunk.data);
-        // println!("this chunk data : {:?},  \nmine data : {:?}", &final_chunk.data[(length+8) as usize..], crc_slice);
-        if final_chunk.crc() != crc {
+        Ok(parsed)
+    }
+}
+
+impl TryFrom<&Vec<u8>> for Chunk {
+    type Error = Error;
+    fn try_from(bytes: &Vec<u8>) -> Result<Self>{
+        let final_chunk = Chunk::parse_unchecked(bytes)?;
+        if !final_chunk.crc_matches() {
             return Err(Error::from("checksum mismatch"));
         }
-        
+
         Ok(final_chunk)
     }
 } 
@@ -139,6 +333,14 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_try_new_chunk() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = "This is where your secret message will be!".as_bytes().to_vec();
+        let chunk = Chunk::try_new(chunk_type, data).unwrap();
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
@@ -165,6 +367,12 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_chunk_stored_crc() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.stored_crc(), 2882656334);
+    }
+
     #[test]
     fn test_valid_chunk_from_bytes() {
         let data_length: u32 = 42;
@@ -213,6 +421,109 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_parse_unchecked_keeps_bad_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let end = bytes.len();
+        bytes[end - 1] ^= 1;
+
+        let chunk = Chunk::parse_unchecked(&bytes).unwrap();
+
+        assert!(!chunk.crc_matches());
+        assert_eq!(chunk.stored_crc(), 2882656334 ^ 1);
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.as_bytes(), bytes);
+        assert!(Chunk::parse_unchecked(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn test_set_data_updates_length_and_crc() {
+        let mut chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![]);
+        chunk.set_data("This is where your secret message will be!".as_bytes().to_vec());
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.stored_crc(), 2882656334);
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+    }
+
+    #[test]
+    fn test_try_set_data() {
+        let mut chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![]);
+        chunk.try_set_data("This is where your secret message will be!".as_bytes().to_vec()).unwrap();
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+    }
+
+    #[test]
+    fn test_data_mut_recomputes_crc_on_drop() {
+        let mut chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"This is where".to_vec());
+        let mut data = chunk.data_mut();
+        data.extend_from_slice(b" your secret message will be!");
+        assert_eq!(data.len(), 42);
+        drop(data);
+        assert!(chunk.crc_matches());
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+
+        chunk.data_mut().clear();
+        assert_eq!(chunk.length(), 0);
+        assert!(chunk.crc_matches());
+    }
+
+    #[test]
+    fn test_data_mut_fixes_bad_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let end = bytes.len();
+        bytes[end - 1] ^= 1;
+        let mut chunk = Chunk::parse_unchecked(&bytes).unwrap();
+
+        chunk.data_mut()[0] = b't';
+        assert!(chunk.crc_matches());
+        assert_eq!(chunk.data_as_string().unwrap(), "this is where your secret message will be!");
+    }
+
+    #[test]
+    fn test_with_type() {
+        let chunk = testing_chunk().with_type(ChunkType::from_str("ruSt").unwrap());
+        assert_eq!(chunk.chunk_type().to_string(), "ruSt");
+        assert_eq!(chunk.length(), 42);
+        assert!(chunk.crc_matches());
+        assert_ne!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_chunk_serde() {
+        let chunk = testing_chunk();
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "RuSt", "text": "This is where your secret message will be!"}));
+        assert_eq!(serde_json::from_value::<Chunk>(json).unwrap().as_bytes(), chunk.as_bytes());
+
+        let binary = Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0, 0, 177, 143]);
+        let json = serde_json::to_value(&binary).unwrap();
+        assert_eq!(json["data"], "AACxjw==");
+        assert_eq!(json["fields"]["gamma"], "0.45455");
+        assert_eq!(serde_json::from_value::<Chunk>(json).unwrap().as_bytes(), binary.as_bytes());
+    }
+
+    #[test]
+    fn test_chunk_serde_keeps_bad_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let end = bytes.len();
+        bytes[end - 1] ^= 1;
+        let chunk = Chunk::parse_unchecked(&bytes).unwrap();
+
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["crc"], 2882656334u32 ^ 1);
+        assert_eq!(serde_json::from_value::<Chunk>(json).unwrap().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_chunk_deserialize_rejects_bad_input() {
+        let both = serde_json::json!({"type": "ruSt", "text": "a", "data": "YQ=="});
+        assert!(serde_json::from_value::<Chunk>(both).is_err());
+        let bad_base64 = serde_json::json!({"type": "ruSt", "data": "not base64!"});
+        assert!(serde_json::from_value::<Chunk>(bad_base64).is_err());
+        let typo = serde_json::json!({"type": "ruSt", "txt": "a"});
+        assert!(serde_json::from_value::<Chunk>(typo).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -1,22 +1,61 @@
 
-#![allow(unused_variables)]
 use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::registry::{self, ChunkInfo};
 use crate::{Error, Result};
 
 /// A validated PNG chunk type. See the PNG spec for more details.
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, Clone, PartialEq, Eq)]
-
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ChunkType {
-    pub data: [u8;4],
+    data: [u8;4],
 }
 
-
-#[allow(dead_code)]
 impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType { data: *b"IHDR" };
+    pub const PLTE: ChunkType = ChunkType { data: *b"PLTE" };
+    pub const IDAT: ChunkType = ChunkType { data: *b"IDAT" };
+    pub const IEND: ChunkType = ChunkType { data: *b"IEND" };
+    pub const TRNS: ChunkType = ChunkType { data: *b"tRNS" };
+
+    /// Builds a type from the letters of `name_hint`, changing their case so the type has the
+    /// given properties. The reserved bit is always made valid.
+    ///
+    /// ```
+    /// use pngme::ChunkType;
+    ///
+    /// let chunk_type = ChunkType::from_properties("rust", false, false, true)?;
+    /// assert_eq!(chunk_type.to_string(), "ruSt");
+    /// # Ok::<(), pngme::Error>(())
+    /// ```
+    pub fn from_properties(name_hint: &str, critical: bool, public: bool, safe_to_copy: bool) -> Result<ChunkType> {
+        let mut data = ChunkType::from_str(name_hint)?.data;
+        let case = |byte: u8, upper: bool| if upper { byte.to_ascii_uppercase() } else { byte.to_ascii_lowercase() };
+        data[0] = case(data[0], critical);
+        data[1] = case(data[1], public);
+        data[2] = case(data[2], true);
+        data[3] = case(data[3], !safe_to_copy);
+        Ok(ChunkType { data })
+    }
+
+    /// A random private, ancillary, safe-to-copy type that the registry does not list, so
+    /// hidden data does not stand out by always having the same chunk name
+    pub fn random_private(rng: &mut impl Rng) -> ChunkType {
+        loop {
+            let letters: String = (0..4).map(|_| rng.gen_range('a'..='z')).collect();
+            let chunk_type = ChunkType::from_properties(&letters, false, false, true)
+                .expect("four letters always make a chunk type");
+            if chunk_type.info().is_none() {
+                return chunk_type;
+            }
+        }
+    }
+
     /// Returns the raw bytes contained in this chunk
     pub fn bytes(&self) -> [u8; 4] {
         self.data
@@ -42,6 +81,12 @@ impl ChunkType {
         self.data[3].is_ascii_lowercase()
     }
 
+    /// What the registry knows about this type: description, source, how many chunks of it
+    /// a file may have and where they go. `None` for types the registry does not list.
+    pub fn info(&self) -> Option<&'static ChunkInfo> {
+        registry::lookup(self)
+    }
+
     /// Returns true if the reserved byte is valid and all four bytes are represented by the characters A-Z or a-z.
     /// Note that this chunk type should always be valid as it is validated during construction.
     pub fn is_valid(&self) -> bool {
@@ -70,7 +115,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
 
 impl fmt::Display for ChunkType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f,"{}",String::from_utf8(self.data.try_into().unwrap()).unwrap())
+        write!(f,"{}",String::from_utf8(self.data.into()).unwrap())
     }
 }
 
@@ -78,17 +123,39 @@ impl FromStr for ChunkType {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        return if s.chars().any(|val| val.is_numeric()) {
+        if s.chars().any(|val| val.is_numeric()) {
              Err(Error::from("numbers are not allowed pal!"))
         }else {
-        Ok(ChunkType {
-             data: s.as_bytes().try_into().unwrap()
-        })
+        let data: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| Error::from(format!("chunk type `{}` must be exactly 4 letters", s)))?;
+        if !data.iter().all(u8::is_ascii_alphabetic) {
+            return Err(Error::from(format!("chunk type `{}` must be exactly 4 letters", s)));
+        }
+        Ok(ChunkType { data })
         }
     }
 }
 
+/// Serialized as its four letters
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
 
+impl<'de> Deserialize<'de> for ChunkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let bytes: [u8; 4] = name
+            .as_bytes()
+            .try_into()
+            .map_err(|_| serde::de::Error::custom(format!("chunk type `{}` must be exactly 4 letters", name)))?;
+        ChunkType::try_from(bytes)
+            .map_err(|err| serde::de::Error::custom(format!("invalid chunk type `{}`: {}", name, err)))
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -104,6 +171,16 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_chunk_type_serde() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let json = serde_json::to_string(&chunk_type).unwrap();
+        assert_eq!(json, "\"RuSt\"");
+        assert_eq!(serde_json::from_str::<ChunkType>(&json).unwrap(), chunk_type);
+        assert!(serde_json::from_str::<ChunkType>("\"Rust!\"").is_err());
+        assert!(serde_json::from_str::<ChunkType>("\"Ru1t\"").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -174,6 +251,38 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_from_str_wrong_length() {
+        assert!(ChunkType::from_str("Rus").is_err());
+        assert!(ChunkType::from_str("RuStt").is_err());
+        assert!(ChunkType::from_str("Ru t").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_properties() {
+        let chunk_type = ChunkType::from_properties("rust", true, true, false).unwrap();
+        assert_eq!(chunk_type.to_string(), "RUST");
+        let chunk_type = ChunkType::from_properties("RUST", false, false, true).unwrap();
+        assert_eq!(chunk_type.to_string(), "ruSt");
+        assert!(chunk_type.is_valid());
+        assert!(ChunkType::from_properties("rs", false, false, true).is_err());
+        assert!(ChunkType::from_properties("r5t!", false, false, true).is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_random_private() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let chunk_type = ChunkType::random_private(&mut rng);
+            assert!(chunk_type.is_valid() && chunk_type.is_safe_to_copy());
+            assert!(!chunk_type.is_critical() && !chunk_type.is_public());
+            assert!(chunk_type.info().is_none());
+        }
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,75 +1,881 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fs;
-use crc::{Crc, CRC_32_ISO_HDLC};
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
-use crate::chunk::Chunk;
-use crate::png::Png;
-use crate::{Error, Result};
-
-fn make_png (path: &std::path::PathBuf) -> Result<Png> {
-    let png_file_as_bytes = std::fs::read(path)
-        .map_err(|err| Error::from(format!("Error reading `{:?}`: {}", path, err)))?;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::args::{
+    BatchArgs, BuildJsonArgs, DecodeArgs, DiffArgs, DumpArgs, DumpJsonArgs, EncodeArgs, ExtractArgs, ExtractZipArgs, Format, GenerateArgs, InsertArgs, Keep, Method,
+    OutputArgs, PatchApplyArgs, PolyglotArgs, PrintArgs, RemoveArgs, ReorderArgs, RepairCrcArgs, StripArgs, Style, TypesArgs,
+};
+use pngme::atomic;
+use pngme::capacity;
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::describe;
+use pngme::diff;
+use pngme::generate;
+use crate::batch;
+use crate::hexdump::hexdump;
+use pngme::message::{self, Location};
+use pngme::order;
+use pngme::patch::Patch;
+use crate::output::{
+    self, CapacityOutput, DecodeOutput, DiffOutput, DumpFileOutput, DumpOutput, EstimateOutput, PngSummary, ScanOutput,
+    TypeOutput, ValidateOutput,
+};
+use pngme::pixels::Header;
+use pngme::png::Png;
+use pngme::registry;
+use pngme::scan;
+use pngme::strip::{self, Policy};
+use pngme::zip;
+use pngme::{Error, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// True for the `-` path, which stands for stdin when reading and stdout when writing
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Reads a whole file, or stdin for `-`
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let read = if is_stdio(path) {
+        io::stdin().lock().read_to_end(&mut bytes).map(|_| ())
+    } else {
+        fs::read(path).map(|file| bytes = file)
+    };
+    read.map_err(|err| Error::from(format!("Error reading `{:?}`: {}", path, err)))?;
+    Ok(bytes)
+}
+
+/// Writes bytes to `out` (which ends up on stdout) for `-`, otherwise atomically to the file
+fn write_output(path: &Path, bytes: &[u8], backup: Option<&str>, out: &mut Vec<u8>) -> Result<()> {
+    if is_stdio(path) {
+        out.extend_from_slice(bytes);
+        return Ok(());
+    }
+    atomic::write_atomic(path, bytes, backup)
+        .map_err(|err| Error::from(format!("failed to write {:?}: {}", path, err)))
+}
+
+/// Where to write the result for `input`: `output` itself, or a file of the same name
+/// inside it when `output` is a directory
+fn output_path(output: &Path, input: &Path) -> PathBuf {
+    match input.file_name() {
+        Some(name) if output.is_dir() => output.join(name),
+        _ => output.to_path_buf(),
+    }
+}
+
+fn make_png (path: &Path) -> Result<Png> {
+    let png_file_as_bytes = read_input(path)?;
     let png = Png::try_from(&png_file_as_bytes[..])
         .map_err(|err| Error::from(format!("invalid png file`{:?}`: {}", path, err)))?;
     Ok(png)
 }
 
-/// Encodes a message into a PNG file and saves the result
-pub fn encode(args: EncodeArgs) -> Result<()> {
-    let mut png  = make_png(&args.path)?;
-    let data_length: u32 = args.message.len() as u32;
-    let message_bytes = args.message.as_bytes();
-    let chunk_type = args.chunk_type.as_bytes();
-    const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-    let mut data: Vec<u8> = data_length
-        .to_be_bytes()
-        .iter()
-        .chain(chunk_type.iter())
-        .chain(message_bytes.iter())
-        .copied()
-        .collect();
-    let buffer = &data[4..(data_length as usize + 8)];
-    let crc = CRC.checksum(buffer);
-    data.extend(crc.to_be_bytes().iter());
-    let chunk_data = Chunk::try_from(data.as_ref()).map_err(|err| {
-        Error::from(format!(
-            "Invalid chunk_type`{:?}`: {}",
-            args.chunk_type, err
-        ))
-    })?;
-
-    png.append_chunk(chunk_data);
-
-    fs::write(&args.path, png.as_bytes())
-        .map_err(|err| Error::from(format!("failed to write`{:?}`: {}", &args.path, err)))?;
+/// Writes the png to `--output` if given, otherwise replaces `path` atomically,
+/// keeping a copy of the original when `--backup` is set
+fn save_png(png: &Png, path: &Path, output: &OutputArgs, out: &mut Vec<u8>) -> Result<()> {
+    let target = output.output.as_deref().map_or_else(|| path.to_path_buf(), |o| output_path(o, path));
+    let backup = if target == path { output.backup.as_deref() } else { None };
+    write_output(&target, &png.as_bytes(), backup, out)
+}
+
+/// With several input files a single `--output` file would be overwritten by each of them,
+/// and so would a file in an `--output` directory when two inputs share a file name, as
+/// `a/x.png` and `b/x.png` found by `--recursive` do
+pub fn check_output(output: Option<&Path>, patterns: &[PathBuf], batch: &BatchArgs) -> Result<()> {
+    let Some(output) = output else { return Ok(()) };
+    if !batch::is_batch(patterns) {
+        return Ok(());
+    }
+    if !output.is_dir() {
+        return Err(Error::from(format!("`{:?}` must be an existing directory when processing several files", output)));
+    }
+    let mut seen: HashMap<&OsStr, &Path> = HashMap::new();
+    let files = batch::expand(patterns, batch.recursive).files;
+    for file in &files {
+        let Some(name) = file.file_name() else { continue };
+        if let Some(other) = seen.insert(name, file) {
+            return Err(Error::from(format!(
+                "`{}` and `{}` would both be written to `{}`",
+                other.display(),
+                file.display(),
+                output.join(name).display()
+            )));
+        }
+    }
     Ok(())
 }
 
-///Searches for a message hidden in a PNG file and prints the message if one is found
-pub fn decode(args: DecodeArgs) -> Result<()> {
-    let png = make_png(&args.path)?;
-    let encoded_message = png.chunk_by_type(&args.chunk_type[..]).unwrap();
-    let decoded_message = encoded_message.data_as_string().unwrap();
+/// True when `save_png` will write the image to stdout, so reports must go to stderr instead
+fn saves_to_stdout(path: &Path, output: &OutputArgs) -> bool {
+    is_stdio(output.output.as_deref().unwrap_or(path))
+}
+
+/// The payload to hide: `--message` as is, or the contents of `--message-file`.
+/// Read once up front so that every file of a batch gets the same payload.
+pub fn read_message(args: &EncodeArgs) -> Result<Vec<u8>> {
+    match (&args.message, &args.message_file) {
+        (Some(message), _) => Ok(message.as_bytes().to_vec()),
+        (None, Some(file)) => {
+            if is_stdio(file) && args.path.iter().any(|p| is_stdio(p)) {
+                return Err(Error::from("the image and the message cannot both come from stdin"));
+            }
+            read_input(file)
+        }
+        (None, None) => Err(Error::from("either --message or --message-file is required")),
+    }
+}
+
+/// The data for `insert`, read once up front like the message for `encode`. `Chunk::try_new`
+/// refuses data too long for a chunk when it is inserted.
+pub fn read_chunk_data(args: &InsertArgs) -> Result<Vec<u8>> {
+    if is_stdio(&args.data_file) && args.path.iter().any(|p| is_stdio(p)) {
+        return Err(Error::from("the image and the chunk data cannot both come from stdin"));
+    }
+    read_input(&args.data_file)
+}
+
+/// Parses a chunk type given on the command line, rejecting anything that is not four valid letters
+fn parse_chunk_type(chunk_type: &str) -> Result<ChunkType> {
+    let bytes: [u8; 4] = chunk_type
+        .as_bytes()
+        .try_into()
+        .map_err(|_| Error::from(format!("chunk type `{}` must be exactly 4 letters", chunk_type)))?;
+    ChunkType::try_from(bytes).map_err(|err| Error::from(format!("invalid chunk type `{}`: {}", chunk_type, err)))
+}
+
+/// Where `--method` and `--chunk-type` say the message goes
+fn location(method: Method, chunk_type: &str) -> Result<Location> {
+    match method {
+        Method::Chunk => Ok(Location::Chunk(parse_chunk_type(chunk_type)?)),
+        Method::Trailer => Ok(Location::Trailer),
+    }
+}
 
-    println!("{:?}", decoded_message);
+/// The chunk type a new message goes in. Only letters are checked here so that
+/// `message::check_chunk_type` can explain why a type is refused; its warnings go to stderr.
+fn message_chunk_type(chunk_type: &str) -> Result<ChunkType> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    for warning in message::check_chunk_type(&chunk_type)? {
+        eprintln!("warning: {}", warning);
+    }
+    Ok(chunk_type)
+}
 
+/// Where `encode` hides the message, or `None` when `--random-type` draws a new type for
+/// every file. Decided once, so a batch reports a problem with the type only once.
+pub fn encode_location(args: &EncodeArgs) -> Result<Option<Location>> {
+    match args.method {
+        Method::Trailer => Ok(Some(Location::Trailer)),
+        Method::Chunk if args.random_type => Ok(None),
+        Method::Chunk => Ok(Some(Location::Chunk(message_chunk_type(&args.chunk_type)?))),
+    }
+}
+
+/// Encodes a message into a PNG file and saves the result
+pub fn encode(args: &EncodeArgs, location: Option<&Location>, message_bytes: &[u8], path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let mut png  = make_png(path)?;
+    let location = match location {
+        Some(location) => location.clone(),
+        None => {
+            let chunk_type = ChunkType::random_private(&mut StdRng::from_entropy());
+            let report = format!("hid the message in a `{}` chunk", chunk_type);
+            if saves_to_stdout(path, &args.output) {
+                eprintln!("{}", report);
+            } else {
+                writeln!(out, "{}", report)?;
+            }
+            Location::Chunk(chunk_type)
+        }
+    };
+    if location == Location::Trailer && !png.trailer().is_empty() {
+        eprintln!("warning: replacing {} existing bytes after IEND in `{:?}`", png.trailer().len(), path);
+    }
+    message::encode(&mut png, &location, message_bytes)?;
+
+    save_png(&png, path, &args.output, out)
+}
+
+///Searches for a message hidden in a PNG file and prints the message if one is found
+pub fn decode(args: &DecodeArgs, format: Format, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let location = location(args.method, &args.chunk_type)?;
+    let png = make_png(path)?;
+    let payload = message::decode(&png, &location).map_err(|err| Error::from(format!("{} in `{:?}`", err, path)))?;
+    let chunk_type = match location {
+        Location::Chunk(chunk_type) => Some(chunk_type.to_string()),
+        Location::Trailer => None,
+    };
+    if args.raw {
+        return write_output(Path::new("-"), payload, None, out);
+    }
+    let decoded_message = String::from_utf8_lossy(payload).to_string();
+
+    match format {
+        Format::Text => writeln!(out, "{:?}", decoded_message)?,
+        Format::Json => output::write_json(out, &DecodeOutput {
+            path: path.display().to_string(),
+            method: format!("{:?}", args.method).to_lowercase(),
+            chunk_type,
+            message: decoded_message,
+        })?,
+    }
     Ok(())
 }
 
 /// Removes a chunk from a PNG file and saves the result
-pub fn remove(args: RemoveArgs) -> Result<()> {
-    let mut png = make_png(&args.path)?;
-    png.remove_chunk(&args.chunk_type[..])
+pub fn remove(args: &RemoveArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let location = location(Method::Chunk, &args.chunk_type)?;
+    let mut png = make_png(path)?;
+    message::remove(&mut png, &location)
         .map_err(|err| Error::from(format!("Failed to remove the chunk :{:?} : {}", args.chunk_type,err)))?;
     
-    fs::write(&args.path, png.as_bytes())
-        .map_err(|err| Error::from(format!("failed to write`{:?}`: {}", &args.path, err)))?;
+    save_png(&png, path, &args.output, out)
+}
+
+/// Prints a table of the chunks in a PNG file, optionally only those of one type
+pub fn print_chunks(args: &PrintArgs, format: Format, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let png = make_png(path)?;
+    let filter = args.chunk_type.as_deref().map(parse_chunk_type).transpose()?;
+    if format == Format::Json {
+        let summary = PngSummary::new(path, &png, |chunk| {
+            filter.as_ref().is_none_or(|t| t == chunk.chunk_type())
+        });
+        return output::write_json(out, &summary);
+    }
+
+    writeln!(
+        out,
+        "{:>5}  {:>10}  {:>10}  {:<4}  {:<5}  {:<10}  {:<10}  summary",
+        "index", "offset", "length", "type", "flags", "stored", "computed"
+    )?;
+    for (index, (chunk, offset)) in png.chunks().iter().zip(png.offsets()).enumerate() {
+        if filter.as_ref().is_some_and(|t| t != chunk.chunk_type()) {
+            continue;
+        }
+        let line = format!(
+            "{:>5}  {:>10}  {:>10}  {:<4}  {:<5}  {:08x}    {:08x}    {}",
+            index,
+            offset,
+            chunk.length(),
+            chunk.chunk_type(),
+            describe::flags(chunk.chunk_type()),
+            chunk.stored_crc(),
+            chunk.crc(),
+            describe::summarize(chunk)
+                .or_else(|| chunk.chunk_type().info().map(|info| info.description.to_string()))
+                .unwrap_or_default()
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    writeln!(out, "flags: C critical, P public, R reserved bit valid, S safe to copy")?;
+    if !png.trailer().is_empty() {
+        writeln!(out, "{} bytes after IEND", png.trailer().len())?;
+    }
+    Ok(())
+}
+
+/// Parses a PNG file and reports anything unusual about its structure
+pub fn validate(format: Format, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let checked = make_png(path).and_then(|png| {
+        if let Some(chunk) = png.find(|chunk| chunk.chunk_type().is_critical() && chunk.chunk_type().info().is_none()) {
+            return Err(Error::from(format!(
+                "`{:?}` has an unknown critical chunk `{}`, which decoders must reject",
+                path,
+                chunk.chunk_type()
+            )));
+        }
+        match order::violations(png.chunks()).as_slice() {
+            [] => Ok(png),
+            broken => Err(Error::from(format!("chunks of `{:?}` are out of order: {}", path, broken.join("; ")))),
+        }
+    });
+    let mut result = ValidateOutput {
+        path: path.display().to_string(),
+        valid: checked.is_ok(),
+        chunks: 0,
+        warnings: vec![],
+        error: None,
+    };
+    match &checked {
+        Ok(png) => {
+            result.chunks = png.chunks().len();
+            for chunk in png.chunks() {
+                let chunk_type = chunk.chunk_type();
+                if chunk_type.is_public() && chunk_type.info().is_none() {
+                    result.warnings.push(format!("unregistered public chunk `{}`", chunk_type));
+                }
+            }
+            if !png.trailer().is_empty() {
+                result.warnings.push(format!("{} bytes of data after IEND", png.trailer().len()));
+            }
+        }
+        Err(err) => result.error = Some(err.to_string()),
+    }
+
+    match format {
+        Format::Json => output::write_json(out, &result)?,
+        Format::Text => {
+            for warning in &result.warnings {
+                eprintln!("warning: {:?}: {}", path, warning);
+            }
+            if result.valid {
+                writeln!(out, "{:?}: ok, {} chunks", path, result.chunks)?;
+            }
+        }
+    }
+    checked.map(|_| ())
+}
+
+/// Appends a zip archive after IEND, fixing its offsets so unzip tools still find the entries
+pub fn polyglot(args: &PolyglotArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let mut png = make_png(path)?;
+    let archive = read_input(&args.zip)?;
+    if !png.trailer().is_empty() {
+        eprintln!("warning: replacing {} existing bytes after IEND", png.trailer().len());
+        png.set_trailer(vec![]);
+    }
+    let base = u32::try_from(png.as_bytes().len())
+        .map_err(|_| Error::from("png is too large to carry a zip archive"))?;
+    let archive = zip::rebase(&archive, base)
+        .map_err(|err| Error::from(format!("invalid zip archive `{:?}`: {}", args.zip, err)))?;
+    png.set_trailer(archive);
+
+    write_output(&output_path(&args.output, path), &png.as_bytes(), None, out)
+}
+
+/// Writes the zip archive stored after IEND out as a standalone file
+pub fn extract_zip(args: &ExtractZipArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let png = make_png(path)?;
+    if !zip::is_zip(png.trailer()) {
+        return Err(Error::from(format!("no zip archive after IEND in `{:?}`", path)));
+    }
+    let archive = zip::rebase(png.trailer(), 0)?;
+
+    let target = if args.output.is_dir() {
+        args.output.join(path.with_extension("zip").file_name().unwrap_or_default())
+    } else {
+        args.output.clone()
+    };
+    write_output(&target, &archive, None, out)
+}
+
+/// Scans a file for hidden data, printing its findings and risk score
+pub fn scan(format: Format, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let png = make_png(path)?;
+    let report = scan::scan(&png);
+    if format == Format::Json {
+        return output::write_json(out, &ScanOutput::new(path, &report));
+    }
+    writeln!(out, "{:?}: risk {}/100 ({})", path, report.risk(), report.level())?;
+    match (report.chi_square, report.rs_estimate) {
+        (Some(p), Some(rate)) => writeln!(out, "  chi-square p={:.3}  rs estimate={:.3}", p, rate)?,
+        (Some(p), None) => writeln!(out, "  chi-square p={:.3}", p)?,
+        _ => {}
+    }
+    if let Some(err) = &report.pixel_error {
+        writeln!(out, "  pixel analysis skipped: {}", err)?;
+    }
+    for finding in &report.findings {
+        writeln!(out, "  - [{}] {}", finding.score, finding.message)?;
+    }
     Ok(())
 }
 
-/// Prints all of the chunks in a PNG file
-pub fn print_chunks(args: PrintArgs) -> Result<()> {
-    let png = make_png(&args.path)?;
-    println!("{:?}",png.as_bytes());
+/// Prints the payload capacity of every embedding method for a PNG file
+pub fn capacity(format: Format, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let png = make_png(path)?;
+    let header = Header::from_png(&png)?;
+    let estimates = capacity::estimate(&png)?;
+    if format == Format::Json {
+        return output::write_json(out, &CapacityOutput {
+            path: path.display().to_string(),
+            width: header.width,
+            height: header.height,
+            color_type: header.color_type,
+            bit_depth: header.bit_depth,
+            estimates: estimates.iter().map(EstimateOutput::from).collect(),
+        });
+    }
+    writeln!(
+        out,
+        "{:?}: {}x{}, color type {}, bit depth {}",
+        path, header.width, header.height, header.color_type, header.bit_depth
+    )?;
+    for estimate in estimates {
+        writeln!(out, "  {:<10} {}", estimate.method, estimate.capacity)?;
+    }
+    writeln!(out, "  raw upper bounds: no envelope, encryption or erasure coding overhead is subtracted")?;
     Ok(())
 }
+
+/// Removes non-essential chunks from a PNG file and reports what was dropped
+pub fn strip(args: &StripArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let mut png = make_png(path)?;
+    let parse_all = |types: &[String]| types.iter().map(|t| parse_chunk_type(t)).collect::<Result<Vec<_>>>();
+    let policy = if !args.allow.is_empty() {
+        Policy::Allow(parse_all(&args.allow)?)
+    } else if !args.deny.is_empty() {
+        let deny = parse_all(&args.deny)?;
+        for chunk_type in deny.iter().filter(|t| strip::is_essential(t)) {
+            eprintln!("warning: `{}` is needed to display the image and will be kept", chunk_type);
+        }
+        Policy::Deny(deny)
+    } else {
+        match args.keep {
+            Keep::Critical => Policy::Critical,
+            Keep::Color => Policy::Color,
+        }
+    };
+
+    let report = strip::strip(&mut png, &policy);
+    let to_stderr = saves_to_stdout(path, &args.output);
+    let mut lines = vec![];
+    for removed in &report.removed {
+        lines.push(format!(
+            "removed {} ({} bytes, {})",
+            removed.chunk_type,
+            removed.size(),
+            if removed.chunk_type.is_safe_to_copy() { "safe to copy" } else { "unsafe to copy" }
+        ));
+    }
+    if report.trailer > 0 {
+        lines.push(format!("removed {} bytes after IEND", report.trailer));
+    }
+    lines.push(format!("{} chunks removed, {} bytes saved", report.removed.len(), report.bytes_saved()));
+    for line in lines {
+        if to_stderr {
+            eprintln!("{}", line);
+        } else {
+            writeln!(out, "{}", line)?;
+        }
+    }
+
+    save_png(&png, path, &args.output, out)
+}
+
+/// Prints a hex dump of the length, type, data and CRC fields of the selected chunks
+pub fn dump(args: &DumpArgs, format: Format, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let png = make_png(path)?;
+    let filter = args.chunk_type.as_deref().map(parse_chunk_type).transpose()?;
+    let selected: Vec<(usize, (&Chunk, usize))> = png
+        .chunks()
+        .iter()
+        .zip(png.offsets())
+        .enumerate()
+        .filter(|(index, (chunk, _))| match (&filter, args.index) {
+            (Some(chunk_type), _) => chunk_type == chunk.chunk_type(),
+            (None, Some(wanted)) => *index == wanted,
+            (None, None) => false,
+        })
+        .collect();
+    if selected.is_empty() {
+        return Err(Error::from(format!("no matching chunk in `{:?}`", path)));
+    }
+
+    if format == Format::Json {
+        let chunks: Vec<DumpOutput> = selected
+            .into_iter()
+            .map(|(index, (chunk, offset))| {
+                let (start, end) = dump_range(args, chunk);
+                DumpOutput {
+                    index,
+                    offset,
+                    length: chunk.length(),
+                    chunk_type: chunk.chunk_type().into(),
+                    stored_crc: chunk.stored_crc(),
+                    computed_crc: chunk.crc(),
+                    data_start: start,
+                    data_end: end,
+                    data: chunk.data()[start..end].iter().map(|b| format!("{:02x}", b)).collect(),
+                }
+            })
+            .collect();
+        return output::write_json(out, &DumpFileOutput { path: path.display().to_string(), chunks });
+    }
+
+    for (index, (chunk, offset)) in selected {
+        let bytes = chunk.as_bytes();
+        let length = chunk.length() as usize;
+        writeln!(out, "chunk {} `{}` at offset {}", index, chunk.chunk_type(), offset)?;
+
+        writeln!(out, "-- length (4 bytes): {}", length)?;
+        hexdump(&bytes[0..4], offset).iter().try_for_each(|line| writeln!(out, "{}", line))?;
+
+        writeln!(out, "-- type (4 bytes): {}  flags {}", chunk.chunk_type(), describe::flags(chunk.chunk_type()))?;
+        hexdump(&bytes[4..8], offset + 4).iter().try_for_each(|line| writeln!(out, "{}", line))?;
+
+        let (start, end) = dump_range(args, chunk);
+        if (start, end) == (0, length) {
+            writeln!(out, "-- data ({} bytes)", length)?;
+        } else {
+            writeln!(out, "-- data ({} bytes, showing {}..{})", length, start, end)?;
+        }
+        hexdump(&bytes[8 + start..8 + end], offset + 8 + start).iter().try_for_each(|line| writeln!(out, "{}", line))?;
+
+        writeln!(out, "-- crc (4 bytes): stored {:08x}, computed {:08x}", chunk.stored_crc(), chunk.crc())?;
+        hexdump(&bytes[8 + length..], offset + 8 + length).iter().try_for_each(|line| writeln!(out, "{}", line))?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The part of a chunk's data `--range` selects, clamped to the data
+fn dump_range(args: &DumpArgs, chunk: &Chunk) -> (usize, usize) {
+    let length = chunk.data().len();
+    let range = args.range.clone().unwrap_or(0..length);
+    (range.start.min(length), range.end.min(length))
+}
+
+/// Index of the first chunk of the given type
+fn chunk_position(png: &Png, chunk_type: &ChunkType, path: &Path) -> Result<usize> {
+    png.positions(chunk_type)
+        .first()
+        .copied()
+        .ok_or_else(|| Error::from(format!("no `{}` chunk in `{:?}`", chunk_type, path)))
+}
+
+/// Writes the data of the first chunk of a type (or of all of them) to a file
+pub fn extract(args: &ExtractArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let chunk_type = parse_chunk_type(&args.chunk_type)?;
+    let png = make_png(path)?;
+    let mut matches = png.chunks_by_type(&chunk_type).peekable();
+    if matches.peek().is_none() {
+        return Err(Error::from(format!("no `{}` chunk in `{:?}`", chunk_type, path)));
+    }
+    let data: Vec<u8> = if args.all {
+        matches.flat_map(|chunk| chunk.data().to_vec()).collect()
+    } else {
+        matches.next().map(|chunk| chunk.data().to_vec()).unwrap_or_default()
+    };
+
+    let target = if args.output.is_dir() {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        args.output.join(format!("{}.{}.bin", stem, chunk_type))
+    } else {
+        args.output.clone()
+    };
+    write_output(&target, &data, None, out)?;
+    if !is_stdio(&target) {
+        writeln!(out, "wrote {} bytes of `{}` to {:?}", data.len(), chunk_type, target)?;
+    }
+    Ok(())
+}
+
+/// Adds a chunk holding `data`, with its length and CRC computed, and saves the result
+pub fn insert(args: &InsertArgs, data: &[u8], path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let chunk_type = parse_chunk_type(&args.chunk_type)?;
+    let mut png = make_png(path)?;
+    let index = match (&args.before, &args.after, args.index) {
+        (_, _, Some(index)) => index,
+        (_, Some(after), _) => chunk_position(&png, &parse_chunk_type(after)?, path)? + 1,
+        (Some(before), None, None) => chunk_position(&png, &parse_chunk_type(before)?, path)?,
+        (None, None, None) => chunk_position(&png, &ChunkType::IEND, path)?,
+    };
+    png.insert_chunk(index, Chunk::try_new(chunk_type, data.to_vec())?)
+        .map_err(|err| Error::from(format!("cannot insert into `{:?}`: {}", path, err)))?;
+
+    let end = png.positions(&ChunkType::IEND).first().copied();
+    if index == 0 {
+        eprintln!("warning: `{:?}` no longer starts with IHDR", path);
+    } else if end.is_some_and(|end| end < index) {
+        eprintln!("warning: the new chunk comes after IEND in `{:?}`, most decoders will ignore it", path);
+    }
+    save_png(&png, path, &args.output, out)
+}
+
+/// Moves one chunk, refusing moves that leave the file out of order, or without `--move`
+/// puts every chunk in the recommended order
+pub fn reorder(args: &ReorderArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let mut png = make_png(path)?;
+    let before: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    match (args.from, args.to) {
+        (Some(from), Some(to)) => {
+            let original = png.chunks().to_vec();
+            png.move_chunk(from, to)?;
+            let broken = order::added_violations(&original, png.chunks());
+            if !broken.is_empty() {
+                return Err(Error::from(format!(
+                    "moving chunk {} to {} in `{:?}` would break the chunk order: {}",
+                    from,
+                    to,
+                    path,
+                    broken.join("; ")
+                )));
+            }
+        }
+        _ => order::normalize(&mut png),
+    }
+
+    let after: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    let line = if before == after {
+        format!("chunks of {:?} are already in order", path)
+    } else {
+        format!("new chunk order of {:?}: {}", path, after.join(" "))
+    };
+    if saves_to_stdout(path, &args.output) {
+        eprintln!("{}", line);
+    } else {
+        writeln!(out, "{}", line)?;
+    }
+    for problem in order::violations(png.chunks()) {
+        eprintln!("warning: {:?}: {}", path, problem);
+    }
+    save_png(&png, path, &args.output, out)
+}
+
+/// Reads a PNG without enforcing CRCs, reports every chunk whose stored CRC is wrong and,
+/// unless `--check-only` is given, saves the file with the computed CRCs written in
+pub fn repair_crc(args: &RepairCrcArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let bytes = read_input(path)?;
+    let mut png = Png::parse_unchecked(&bytes)
+        .map_err(|err| Error::from(format!("invalid png file`{:?}`: {}", path, err)))?;
+
+    let to_stderr = saves_to_stdout(path, &args.output) && !args.check_only;
+    let mut lines = vec![];
+    let offsets = png.offsets();
+    for (index, offset) in offsets.into_iter().enumerate() {
+        let chunk = &png.chunks()[index];
+        if chunk.crc_matches() {
+            continue;
+        }
+        lines.push(format!(
+            "{} `{}` chunk {} at offset {}: stored {:08x}, computed {:08x}",
+            if args.check_only { "bad crc in" } else { "fixed" },
+            chunk.chunk_type(),
+            index,
+            offset,
+            chunk.stored_crc(),
+            chunk.crc()
+        ));
+        let fixed = Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec());
+        png.replace_at(index, fixed)?;
+    }
+    let bad = lines.len();
+    if bad == 0 {
+        lines.push(format!("all {} chunks of {:?} have correct CRCs", png.chunks().len(), path));
+    }
+    for line in lines {
+        if to_stderr {
+            eprintln!("{}", line);
+        } else {
+            writeln!(out, "{}", line)?;
+        }
+    }
+
+    match (bad, args.check_only) {
+        (0, _) => Ok(()),
+        (_, true) => Err(Error::from(format!("{} chunks of `{:?}` have bad CRCs", bad, path))),
+        (_, false) => save_png(&png, path, &args.output, out),
+    }
+}
+
+/// Lists the chunks added, removed, moved or modified between two files, and optionally
+/// whether their pixels still match
+pub fn diff(args: &DiffArgs, format: Format, out: &mut Vec<u8>) -> Result<()> {
+    let (old, new) = (make_png(&args.old)?, make_png(&args.new)?);
+    let changes = diff::diff(&old, &new);
+    if let Some(target) = &args.emit_patch {
+        let patch = Patch::between(&old, &new).to_bytes();
+        write_output(target, &patch, None, out)?;
+        if is_stdio(target) {
+            return Ok(());
+        }
+        eprintln!("wrote {} byte patch with {} changes to {:?}", patch.len(), changes.len(), target);
+    }
+    let pixels = args.pixels.then(|| diff::compare_pixels(&old, &new));
+    if format == Format::Json {
+        let result = DiffOutput {
+            old: args.old.display().to_string(),
+            new: args.new.display().to_string(),
+            changes,
+            pixels,
+        };
+        return output::write_json(out, &result);
+    }
+
+    writeln!(out, "--- {}", args.old.display())?;
+    writeln!(out, "+++ {}", args.new.display())?;
+    if changes.is_empty() {
+        writeln!(out, "no chunk differences")?;
+    }
+    for change in &changes {
+        writeln!(out, "{}", change)?;
+    }
+    if let Some(pixels) = pixels {
+        writeln!(out, "{}", pixels)?;
+    }
+    Ok(())
+}
+
+/// Applies a patch made by `diff --emit-patch` and saves the result
+pub fn patch_apply(args: &PatchApplyArgs, out: &mut Vec<u8>) -> Result<()> {
+    if is_stdio(&args.path) && is_stdio(&args.patch) {
+        return Err(Error::from("the image and the patch cannot both come from stdin"));
+    }
+    let patch = Patch::try_from(&read_input(&args.patch)?[..])
+        .map_err(|err| Error::from(format!("invalid patch `{:?}`: {}", args.patch, err)))?;
+    let mut png = make_png(&args.path)?;
+    patch
+        .apply(&mut png)
+        .map_err(|err| Error::from(format!("cannot apply `{:?}` to `{:?}`: {}", args.patch, args.path, err)))?;
+
+    let line = format!("applied {} patch ops to {:?}", patch.ops.len(), args.path);
+    if saves_to_stdout(&args.path, &args.output) {
+        eprintln!("{}", line);
+    } else {
+        writeln!(out, "{}", line)?;
+    }
+    save_png(&png, &args.path, &args.output, out)
+}
+
+/// Makes a carrier image and, when a message is given, hides it in the same step
+pub fn generate(args: &GenerateArgs, out: &mut Vec<u8>) -> Result<()> {
+    let style = match args.style {
+        Style::Noise => generate::Style::Noise,
+        Style::Gradient => generate::Style::Gradient,
+        Style::PhotoLike => generate::Style::PhotoLike,
+    };
+    let mut rng = args.seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let mut png = generate::generate(args.width, args.height, style, &mut rng)?;
+
+    let message = match (&args.message, &args.message_file) {
+        (Some(message), _) => Some(message.as_bytes().to_vec()),
+        (None, Some(file)) => Some(read_input(file)?),
+        (None, None) => None,
+    };
+    if let Some(message) = message {
+        let location = match args.method {
+            Method::Trailer => Location::Trailer,
+            Method::Chunk if args.random_type => {
+                let chunk_type = ChunkType::random_private(&mut rng);
+                eprintln!("hid the message in a `{}` chunk", chunk_type);
+                Location::Chunk(chunk_type)
+            }
+            Method::Chunk => Location::Chunk(message_chunk_type(&args.chunk_type)?),
+        };
+        message::encode(&mut png, &location, &message)?;
+    }
+    write_output(&args.output, &png.as_bytes(), None, out)
+}
+
+/// Writes the whole chunk structure as JSON or YAML. Damaged chunks are kept with their
+/// stored CRC, so `build-json` gives back the exact same file.
+pub fn dump_json(args: &DumpJsonArgs, path: &Path, out: &mut Vec<u8>) -> Result<()> {
+    let bytes = read_input(path)?;
+    let png = Png::parse_unchecked(&bytes).map_err(|err| Error::from(format!("invalid png file`{:?}`: {}", path, err)))?;
+    if args.yaml {
+        let yaml = serde_norway::to_string(&png).map_err(|err| Error::from(format!("failed to serialize output: {}", err)))?;
+        out.extend_from_slice(yaml.as_bytes());
+        return Ok(());
+    }
+    output::write_json(out, &png)
+}
+
+/// Rebuilds a png from a `dump-json` document, in JSON or YAML
+pub fn build_json(args: &BuildJsonArgs, out: &mut Vec<u8>) -> Result<()> {
+    let document = read_input(&args.input)?;
+    let is_json = document.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    let png: Png = if is_json {
+        serde_json::from_slice(&document).map_err(|err| Error::from(format!("invalid JSON in `{:?}`: {}", args.input, err)))?
+    } else {
+        serde_norway::from_slice(&document).map_err(|err| Error::from(format!("invalid YAML in `{:?}`: {}", args.input, err)))?
+    };
+    let violations = order::violations(png.chunks());
+    if !violations.is_empty() {
+        eprintln!("warning: the rebuilt png is not valid: {}", violations.join(", "));
+    }
+    write_output(&args.output, &png.as_bytes(), None, out)
+}
+
+/// Lists the registered chunk types, or describes the given ones whether registered or not
+pub fn types(args: &TypesArgs, format: Format, out: &mut Vec<u8>) -> Result<()> {
+    let types: Vec<ChunkType> = if args.names.is_empty() {
+        registry::REGISTRY.iter().map(|info| parse_chunk_type(info.name)).collect::<Result<_>>()?
+    } else {
+        args.names.iter().map(|name| parse_chunk_type(name)).collect::<Result<_>>()?
+    };
+    let listed: Vec<TypeOutput> = types.iter().map(TypeOutput::from).collect();
+    if format == Format::Json {
+        return output::write_json(out, &listed);
+    }
+
+    writeln!(
+        out,
+        "{:<4}  {:<5}  {:<15}  {:<11}  {:<23}  description",
+        "type", "flags", "source", "count", "placement"
+    )?;
+    for (chunk_type, entry) in types.iter().zip(&listed) {
+        let line = match &entry.info {
+            Some(info) => format!(
+                "{:<4}  {:<5}  {:<15}  {:<11}  {:<23}  {}",
+                chunk_type,
+                describe::flags(chunk_type),
+                info.source,
+                info.multiplicity.to_string(),
+                info.placement.to_string(),
+                info.description
+            ),
+            None => format!("{:<4}  {:<5}  not registered", chunk_type, describe::flags(chunk_type)),
+        };
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out, "flags: C critical, P public, R reserved bit valid, S safe to copy")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    const DICE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/dice.png");
+
+    fn dump_args(index: Option<usize>, chunk_type: Option<&str>, range: Option<Range<usize>>) -> DumpArgs {
+        DumpArgs { path: vec![PathBuf::from(DICE)], index, chunk_type: chunk_type.map(str::to_string), range }
+    }
+
+    #[test]
+    fn test_dump_fields() {
+        let mut out = vec![];
+        dump(&dump_args(Some(0), None, None), Format::Text, Path::new(DICE), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "chunk 0 `IHDR` at offset 8");
+        assert_eq!(lines[1], "-- length (4 bytes): 13");
+        assert!(lines[2].starts_with("00000008  00 00 00 0d"));
+        assert_eq!(lines[3], "-- type (4 bytes): IHDR  flags CPR-");
+        assert!(lines[4].ends_with("|IHDR|"));
+        assert_eq!(lines[5], "-- data (13 bytes)");
+        assert!(lines[6].starts_with("00000010  00 00 01 a4 00 00 01 3b  08 06"));
+        assert_eq!(lines[7], "-- crc (4 bytes): stored 927cf5a3, computed 927cf5a3");
+    }
+
+    #[test]
+    fn test_dump_range() {
+        let mut out = vec![];
+        dump(&dump_args(Some(0), None, Some(4..100)), Format::Text, Path::new(DICE), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("-- data (13 bytes, showing 4..13)\n00000014  00 00 01 3b 08 06"));
+
+        let mut out = vec![];
+        dump(&dump_args(None, Some("IHDR"), Some(0..4)), Format::Json, Path::new(DICE), &mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["chunks"][0]["data"], "000001a4");
+        assert_eq!(value["chunks"][0]["data_end"], 4);
+    }
+
+    #[test]
+    fn test_dump_needs_a_matching_chunk() {
+        let mut out = vec![];
+        assert!(dump(&dump_args(Some(99), None, None), Format::Text, Path::new(DICE), &mut out).is_err());
+        assert!(dump(&dump_args(None, Some("sPLT"), None), Format::Text, Path::new(DICE), &mut out).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::pixels::Header;
+use crate::scan::text_chunk;
+
+/// Longest piece of text shown in a summary before it is cut off
+const PREVIEW_LENGTH: usize = 40;
+
+/// The four property flags of a chunk type as a short string, e.g. `CPR-` for IHDR
+/// (critical, public, reserved bit valid, not safe to copy). Dashes mark unset properties.
+pub fn flags(chunk_type: &ChunkType) -> String {
+    [
+        (chunk_type.is_critical(), 'C'),
+        (chunk_type.is_public(), 'P'),
+        (chunk_type.is_reserved_bit_valid(), 'R'),
+        (chunk_type.is_safe_to_copy(), 'S'),
+    ]
+    .iter()
+    .map(|(set, letter)| if *set { *letter } else { '-' })
+    .collect()
+}
+
+fn color_type_name(color_type: u8) -> &'static str {
+    match color_type {
+        0 => "grayscale",
+        2 => "RGB",
+        3 => "indexed",
+        4 => "grayscale+alpha",
+        6 => "RGBA",
+        _ => "unknown color type",
+    }
+}
+
+fn preview(text: &[u8]) -> String {
+    let text = String::from_utf8_lossy(text);
+    let mut preview: String = text.chars().take(PREVIEW_LENGTH).collect();
+    if text.chars().count() > PREVIEW_LENGTH {
+        preview.push_str("...");
+    }
+    preview.escape_debug().to_string()
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// A one line description of the contents of well known chunk types
+pub fn summarize(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    let summary = match chunk.chunk_type().to_string().as_str() {
+        "IHDR" => {
+            let header = Header::from_chunk(chunk).ok()?;
+            format!(
+                "{}x{}, {}-bit {}, {}",
+                header.width,
+                header.height,
+                header.bit_depth,
+                color_type_name(header.color_type),
+                if header.interlaced { "interlaced" } else { "non-interlaced" }
+            )
+        }
+        "PLTE" => format!("{} entries", data.len() / 3),
+        "tRNS" => format!("{} bytes of transparency", data.len()),
+        "gAMA" => format!("gamma {:.5}", be_u32(data, 0)? as f64 / 100000.0),
+        "cHRM" => format!(
+            "white point {:.4}, {:.4}",
+            be_u32(data, 0)? as f64 / 100000.0,
+            be_u32(data, 4)? as f64 / 100000.0
+        ),
+        "sRGB" => {
+            let intent = match data.first()? {
+                0 => "perceptual",
+                1 => "relative colorimetric",
+                2 => "saturation",
+                3 => "absolute colorimetric",
+                _ => "unknown",
+            };
+            format!("rendering intent {}", intent)
+        }
+        "iCCP" => {
+            let nul = data.iter().position(|b| *b == 0)?;
+            format!("profile `{}`", preview(&data[..nul]))
+        }
+        "bKGD" => {
+            let values: Vec<String> = data.chunks(2).map(|v| be_u16(v, 0).unwrap_or(v[0] as u16).to_string()).collect();
+            format!("background {}", values.join(", "))
+        }
+        "pHYs" => format!(
+            "{} x {} pixels per {}",
+            be_u32(data, 0)?,
+            be_u32(data, 4)?,
+            if *data.get(8)? == 1 { "metre" } else { "unit" }
+        ),
+        "tIME" => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            be_u16(data, 0)?,
+            data.get(2)?,
+            data.get(3)?,
+            data.get(4)?,
+            data.get(5)?,
+            data.get(6)?
+        ),
+        "tEXt" | "zTXt" | "iTXt" => {
+            let (keyword, text) = text_chunk(chunk)?;
+            format!("{}: \"{}\"", keyword, preview(&text))
+        }
+        "IDAT" | "IEND" => return None,
+        _ => format!("{} bytes", data.len()),
+    };
+    Some(summary)
+}
+
+/// The decoded fields of well known chunk types as name and value pairs, finer grained
+/// than `summarize` so two versions of a chunk can be compared field by field
+pub fn fields(chunk: &Chunk) -> Option<Vec<(String, String)>> {
+    let data = chunk.data();
+    let field = |name: &str, value: String| (name.to_string(), value);
+    let fixed = |at: usize| be_u32(data, at).map(|v| format!("{:.5}", v as f64 / 100000.0));
+    let fields = match chunk.chunk_type().to_string().as_str() {
+        "IHDR" => {
+            let header = Header::from_chunk(chunk).ok()?;
+            vec![
+                field("width", header.width.to_string()),
+                field("height", header.height.to_string()),
+                field("bit depth", header.bit_depth.to_string()),
+                field("color type", color_type_name(header.color_type).to_string()),
+                field("interlaced", header.interlaced.to_string()),
+            ]
+        }
+        "PLTE" => {
+            let mut fields = vec![field("entries", (data.len() / 3).to_string())];
+            for (i, entry) in data.chunks(3).enumerate() {
+                let values: Vec<String> = entry.iter().map(u8::to_string).collect();
+                fields.push(field(&format!("entry {}", i), values.join(", ")));
+            }
+            fields
+        }
+        "gAMA" => vec![field("gamma", fixed(0)?)],
+        "cHRM" => {
+            let names = ["white x", "white y", "red x", "red y", "green x", "green y", "blue x", "blue y"];
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| Some(field(name, fixed(i * 4)?)))
+                .collect::<Option<Vec<_>>>()?
+        }
+        "sRGB" => vec![field("rendering intent", data.first()?.to_string())],
+        "iCCP" => {
+            let nul = data.iter().position(|b| *b == 0)?;
+            vec![
+                field("profile name", preview(&data[..nul])),
+                field("compression method", data.get(nul + 1)?.to_string()),
+                field("compressed profile", format!("{} bytes", data.len() - nul - 2)),
+            ]
+        }
+        "bKGD" | "sBIT" | "tRNS" => {
+            let values: Vec<String> = data.iter().map(u8::to_string).collect();
+            vec![field("values", values.join(", "))]
+        }
+        "pHYs" => vec![
+            field("x pixels per unit", be_u32(data, 0)?.to_string()),
+            field("y pixels per unit", be_u32(data, 4)?.to_string()),
+            field("unit", if *data.get(8)? == 1 { "metre" } else { "unknown" }.to_string()),
+        ],
+        "tIME" => vec![field("time", summarize(chunk)?)],
+        "tEXt" | "zTXt" | "iTXt" => {
+            let (keyword, text) = text_chunk(chunk)?;
+            vec![field("keyword", keyword), field("text", preview(&text))]
+        }
+        _ => return None,
+    };
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn summary_of(png: &Png, chunk_type: &str) -> Option<String> {
+        summarize(png.chunks().iter().find(|c| c.chunk_type().to_string() == chunk_type).unwrap())
+    }
+
+    #[test]
+    fn test_flags() {
+        assert_eq!(flags(&ChunkType::from_str("IHDR").unwrap()), "CPR-");
+        assert_eq!(flags(&ChunkType::from_str("ruSt").unwrap()), "--RS");
+    }
+
+    #[test]
+    fn test_summarize_known_types() {
+        let png = Png::try_from(DICE).unwrap();
+
+        assert_eq!(summary_of(&png, "IHDR").unwrap(), "420x315, 8-bit RGBA, non-interlaced");
+        assert!(summary_of(&png, "gAMA").unwrap().starts_with("gamma 0.4545"));
+        assert!(summary_of(&png, "tEXt").unwrap().starts_with("date:create"));
+        assert_eq!(summary_of(&png, "IDAT"), None);
+    }
+
+    #[test]
+    fn test_fields() {
+        let png = Png::try_from(DICE).unwrap();
+        let ihdr = fields(&png.chunks()[0]).unwrap();
+        assert_eq!(ihdr[0], ("width".to_string(), "420".to_string()));
+        assert_eq!(ihdr[3], ("color type".to_string(), "RGBA".to_string()));
+
+        let chrm = png.chunks().iter().find(|c| c.chunk_type().to_string() == "cHRM").unwrap();
+        assert_eq!(fields(chrm).unwrap()[0], ("white x".to_string(), "0.31270".to_string()));
+
+        let unknown = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![1, 2]);
+        assert_eq!(fields(&unknown), None);
+    }
+
+    #[test]
+    fn test_summarize_truncates_long_text() {
+        let data: Vec<u8> = b"Comment\0".iter().chain([b'x'; 100].iter()).copied().collect();
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), data);
+        let summary = summarize(&chunk).unwrap();
+        assert!(summary.ends_with("...\""));
+        assert!(summary.len() < 60);
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::chunk::Chunk;
+use crate::describe;
+use crate::pixels;
+use crate::png::Png;
+
+/// Most differing byte ranges listed for one chunk before the rest are summed up
+const MAX_RANGES: usize = 8;
+
+/// One way the second file differs from the first. Indices are chunk positions in the
+/// file they refer to: `from` in the first file, `to` in the second.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum Change {
+    Removed { chunk_type: String, from: usize },
+    Added { chunk_type: String, to: usize },
+    Moved { chunk_type: String, from: usize, to: usize },
+    Modified { chunk_type: String, from: usize, to: usize, differences: Vec<String> },
+    Trailer { from: usize, to: usize },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Removed { chunk_type, from } => write!(f, "- removed `{}` chunk {}", chunk_type, from),
+            Change::Added { chunk_type, to } => write!(f, "+ added `{}` chunk {}", chunk_type, to),
+            Change::Moved { chunk_type, from, to } => write!(f, "~ moved `{}` chunk {} -> {}", chunk_type, from, to),
+            Change::Modified { chunk_type, from, to, differences } => {
+                write!(f, "* modified `{}` chunk {} -> {}", chunk_type, from, to)?;
+                for difference in differences {
+                    write!(f, "\n    {}", difference)?;
+                }
+                Ok(())
+            }
+            Change::Trailer { from, to } => write!(f, "* data after IEND changed, {} -> {} bytes", from, to),
+        }
+    }
+}
+
+/// How the decoded images of the two files compare
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum PixelComparison {
+    Identical,
+    SizeMismatch { from: (usize, usize), to: (usize, usize) },
+    Different { pixels: usize, total: usize, max_difference: u16 },
+    Undecodable { error: String },
+}
+
+impl fmt::Display for PixelComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PixelComparison::Identical => write!(f, "pixels: identical"),
+            PixelComparison::SizeMismatch { from, to } => {
+                write!(f, "pixels: size differs, {}x{} -> {}x{}", from.0, from.1, to.0, to.1)
+            }
+            PixelComparison::Different { pixels, total, max_difference } => write!(
+                f,
+                "pixels: {} of {} differ, largest channel difference {} of 65535",
+                pixels, total, max_difference
+            ),
+            PixelComparison::Undecodable { error } => write!(f, "pixels: cannot compare, {}", error),
+        }
+    }
+}
+
+/// Ranges of positions where two byte strings differ, up to the shorter length
+fn byte_ranges(a: &[u8], b: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for i in (0..a.len().min(b.len())).filter(|&i| a[i] != b[i]) {
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+/// Describes how the data of two chunks of the same type differs: field by field for
+/// types `describe` understands, as differing byte ranges for everything else
+fn differences(a: &Chunk, b: &Chunk) -> Vec<String> {
+    let mut found = vec![];
+    if a.length() != b.length() {
+        found.push(format!("length: {} -> {}", a.length(), b.length()));
+    }
+    if let (Some(from), Some(to)) = (describe::fields(a), describe::fields(b)) {
+        let old: HashMap<&String, &String> = from.iter().map(|(name, value)| (name, value)).collect();
+        let new: HashMap<&String, &String> = to.iter().map(|(name, value)| (name, value)).collect();
+        for (name, value) in &from {
+            match new.get(name) {
+                Some(other) if *other != value => found.push(format!("{}: {} -> {}", name, value, other)),
+                None => found.push(format!("{}: {} -> (none)", name, value)),
+                _ => {}
+            }
+        }
+        for (name, value) in to.iter().filter(|(name, _)| !old.contains_key(name)) {
+            found.push(format!("{}: (none) -> {}", name, value));
+        }
+    }
+
+    // fields can hide a change, e.g. in a compressed profile or a long text
+    if found.is_empty() || describe::fields(a).is_none() {
+        let ranges = byte_ranges(a.data(), b.data());
+        for range in ranges.iter().take(MAX_RANGES) {
+            found.push(format!("bytes {}..{} differ", range.start, range.end));
+        }
+        if ranges.len() > MAX_RANGES {
+            found.push(format!("and {} more differing ranges", ranges.len() - MAX_RANGES));
+        }
+    }
+    found
+}
+
+/// Marks the elements of the longest strictly increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> Vec<bool> {
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; values.len()];
+    for i in 0..values.len() {
+        let at = tails.partition_point(|&t| values[t] < values[i]);
+        if at > 0 {
+            previous[i] = Some(tails[at - 1]);
+        }
+        if at == tails.len() {
+            tails.push(i);
+        } else {
+            tails[at] = i;
+        }
+    }
+    let mut keep = vec![false; values.len()];
+    let mut current = tails.last().copied();
+    while let Some(i) = current {
+        keep[i] = true;
+        current = previous[i];
+    }
+    keep
+}
+
+/// Pairs each chunk with its type and how many chunks of that type came before it, which
+/// is what chunks are matched on between the two files
+fn keys(png: &Png) -> Vec<(String, usize)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    png.chunks()
+        .iter()
+        .map(|chunk| {
+            let name = chunk.chunk_type().to_string();
+            let count = seen.entry(name.clone()).or_default();
+            *count += 1;
+            (name, *count - 1)
+        })
+        .collect()
+}
+
+/// Aligns the chunks of two files by type and position. Returns, in the order of the first
+/// file, the index of every chunk that has a counterpart in the second file, that
+/// counterpart's index, and whether the chunk kept its place, i.e. belongs to the longest
+/// run of matched chunks that kept their relative order.
+pub fn align(a: &Png, b: &Png) -> Vec<(usize, usize, bool)> {
+    let to_keys = keys(b);
+    let to_index: HashMap<&(String, usize), usize> = to_keys.iter().enumerate().map(|(i, key)| (key, i)).collect();
+    let matched: Vec<(usize, usize)> = keys(a)
+        .iter()
+        .enumerate()
+        .filter_map(|(from, key)| to_index.get(key).map(|&to| (from, to)))
+        .collect();
+    let in_order = longest_increasing(&matched.iter().map(|(_, to)| *to).collect::<Vec<_>>());
+    matched.into_iter().zip(in_order).map(|((from, to), kept)| (from, to, kept)).collect()
+}
+
+/// Lists what was removed, added, moved or modified between two files. Matched chunks that
+/// did not keep their place count as moved.
+pub fn diff(a: &Png, b: &Png) -> Vec<Change> {
+    let (from_keys, to_keys) = (keys(a), keys(b));
+    let mut changes = vec![];
+    let mut matched = align(a, b).into_iter().peekable();
+    for (from, (chunk_type, _)) in from_keys.iter().enumerate() {
+        let Some((_, to, kept)) = matched.next_if(|(f, _, _)| *f == from) else {
+            changes.push(Change::Removed { chunk_type: chunk_type.clone(), from });
+            continue;
+        };
+        if !kept {
+            changes.push(Change::Moved { chunk_type: chunk_type.clone(), from, to });
+        }
+        let (old, new) = (&a.chunks()[from], &b.chunks()[to]);
+        if old.as_bytes() != new.as_bytes() {
+            let differences = differences(old, new);
+            changes.push(Change::Modified { chunk_type: chunk_type.clone(), from, to, differences });
+        }
+    }
+    let from_set: HashMap<&(String, usize), usize> = from_keys.iter().enumerate().map(|(i, key)| (key, i)).collect();
+    for (to, key) in to_keys.iter().enumerate().filter(|(_, key)| !from_set.contains_key(key)) {
+        changes.push(Change::Added { chunk_type: key.0.clone(), to });
+    }
+    if a.trailer() != b.trailer() {
+        changes.push(Change::Trailer { from: a.trailer().len(), to: b.trailer().len() });
+    }
+    changes
+}
+
+/// Decodes both images to 16 bit RGBA and compares them pixel by pixel. Fully transparent
+/// pixels count as equal whatever their color.
+pub fn compare_pixels(a: &Png, b: &Png) -> PixelComparison {
+    let decode = |png: &Png| pixels::decode(png).and_then(|image| Ok((image.width(), image.height(), pixels::rgba(png, &image)?)));
+    let ((from_width, from_height, from), (to_width, to_height, to)) = match (decode(a), decode(b)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(err), _) | (_, Err(err)) => return PixelComparison::Undecodable { error: err.to_string() },
+    };
+    if (from_width, from_height) != (to_width, to_height) {
+        return PixelComparison::SizeMismatch { from: (from_width, from_height), to: (to_width, to_height) };
+    }
+
+    let mut differing = 0;
+    let mut max_difference = 0;
+    for (p, q) in from.iter().zip(&to) {
+        if p == q || (p[3] == 0 && q[3] == 0) {
+            continue;
+        }
+        differing += 1;
+        let largest = p.iter().zip(q).map(|(x, y)| x.abs_diff(*y)).max().unwrap_or(0);
+        max_difference = max_difference.max(largest);
+    }
+    match differing {
+        0 => PixelComparison::Identical,
+        pixels => PixelComparison::Different { pixels, total: from.len(), max_difference },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_identical_files() {
+        let png = Png::try_from(DICE).unwrap();
+        assert!(diff(&png, &png).is_empty());
+        assert_eq!(compare_pixels(&png, &png), PixelComparison::Identical);
+    }
+
+    #[test]
+    fn test_added_removed_and_moved() {
+        let a = Png::from_chunks(vec![chunk("IHDR", b""), chunk("tEXt", b"a\0b"), chunk("gAMA", b"1234"), chunk("IEND", b"")]);
+        let b = Png::from_chunks(vec![chunk("IHDR", b""), chunk("gAMA", b"1234"), chunk("ruSt", b"x"), chunk("IEND", b"")]);
+
+        assert_eq!(
+            diff(&a, &b),
+            vec![
+                Change::Removed { chunk_type: "tEXt".to_string(), from: 1 },
+                Change::Added { chunk_type: "ruSt".to_string(), to: 2 },
+            ]
+        );
+
+        let c = Png::from_chunks(vec![chunk("gAMA", b"1234"), chunk("IHDR", b""), chunk("tEXt", b"a\0b"), chunk("IEND", b"")]);
+        assert_eq!(diff(&a, &c), vec![Change::Moved { chunk_type: "gAMA".to_string(), from: 2, to: 0 }]);
+    }
+
+    #[test]
+    fn test_modified_fields_and_bytes() {
+        let a = Png::from_chunks(vec![chunk("gAMA", &45455u32.to_be_bytes()), chunk("ruSt", b"hello world")]);
+        let b = Png::from_chunks(vec![chunk("gAMA", &100000u32.to_be_bytes()), chunk("ruSt", b"hallo wurld")]);
+
+        let changes = diff(&a, &b);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Modified {
+                    chunk_type: "gAMA".to_string(),
+                    from: 0,
+                    to: 0,
+                    differences: vec!["gamma: 0.45455 -> 1.00000".to_string()]
+                },
+                Change::Modified {
+                    chunk_type: "ruSt".to_string(),
+                    from: 1,
+                    to: 1,
+                    differences: vec!["bytes 1..2 differ".to_string(), "bytes 7..8 differ".to_string()]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_longest_increasing() {
+        assert_eq!(longest_increasing(&[0, 3, 1, 2]), vec![true, false, true, true]);
+        assert_eq!(longest_increasing(&[]), Vec::<bool>::new());
+    }
+
+    #[test]
+    fn test_compare_pixels_ignores_chunk_layout() {
+        let a = Png::try_from(DICE).unwrap();
+        let mut chunks: Vec<Chunk> = a.chunks().iter().filter(|c| c.chunk_type().to_string() != "tEXt").map(|c| Chunk::parse_unchecked(&c.as_bytes()).unwrap()).collect();
+        chunks.swap(1, 2);
+        let b = Png::from_chunks(chunks);
+
+        assert_eq!(compare_pixels(&a, &b), PixelComparison::Identical);
+    }
+}
@@ -0,0 +1,191 @@
+use rand::Rng;
+
+use crate::builder::PngBuilder;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Largest width or height `generate` will make, to keep memory use reasonable
+pub const MAX_SIDE: u32 = 1 << 14;
+
+/// What a generated carrier looks like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// A fine, even texture around one random color
+    Noise,
+    /// A smooth blend between two random colors
+    Gradient,
+    /// Sky above uneven, textured ground, with a vignette
+    PhotoLike,
+}
+
+/// Roughly normal noise with the given standard deviation, from the sum of three uniform
+/// samples; camera sensors add grain like this, so the low bits of every sample vary the
+/// way they do in real photographs
+fn grain(rng: &mut impl Rng, sigma: f32) -> f32 {
+    let sum: f32 = (0..3).map(|_| rng.gen::<f32>()).sum();
+    (sum - 1.5) * 2.0 * sigma
+}
+
+/// Smooth random values in 0..1, one per pixel, from several octaves of a random lattice
+/// interpolated between its points
+fn fractal_noise(width: usize, height: usize, rng: &mut impl Rng) -> Vec<f32> {
+    let mut values = vec![0f32; width * height];
+    let (mut cell, mut amplitude, mut total) = (width.max(height) as f32 / 2.0, 1.0, 0.0);
+    while cell >= 2.0 {
+        let (columns, rows) = ((width as f32 / cell) as usize + 2, (height as f32 / cell) as usize + 2);
+        let lattice: Vec<f32> = (0..columns * rows).map(|_| rng.gen()).collect();
+        for y in 0..height {
+            let (fy, ty) = ((y as f32 / cell).floor(), smoothstep((y as f32 / cell).fract()));
+            for x in 0..width {
+                let (fx, tx) = ((x as f32 / cell).floor(), smoothstep((x as f32 / cell).fract()));
+                let at = |dx: usize, dy: usize| lattice[(fy as usize + dy) * columns + fx as usize + dx];
+                let top = lerp(at(0, 0), at(1, 0), tx);
+                let bottom = lerp(at(0, 1), at(1, 1), tx);
+                values[y * width + x] += amplitude * lerp(top, bottom, ty);
+            }
+        }
+        total += amplitude;
+        cell /= 2.0;
+        amplitude /= 2.0;
+    }
+    if total > 0.0 {
+        values.iter_mut().for_each(|value| *value /= total);
+    }
+    values
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [lerp(a[0], b[0], t), lerp(a[1], b[1], t), lerp(a[2], b[2], t)]
+}
+
+fn random_color(rng: &mut impl Rng, low: f32, high: f32) -> [f32; 3] {
+    [rng.gen_range(low..high), rng.gen_range(low..high), rng.gen_range(low..high)]
+}
+
+/// Colors of every pixel in raster order, before grain is added
+fn base_colors(width: usize, height: usize, style: Style, rng: &mut impl Rng) -> Vec<[f32; 3]> {
+    match style {
+        Style::Noise => {
+            let color = random_color(rng, 60.0, 200.0);
+            let texture = fractal_noise(width, height, rng);
+            texture.iter().map(|t| color.map(|c| c + (t - 0.5) * 60.0)).collect()
+        }
+        Style::Gradient => {
+            let (from, to) = (random_color(rng, 0.0, 255.0), random_color(rng, 0.0, 255.0));
+            let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+            let (dx, dy) = (angle.cos(), angle.sin());
+            // project the corners to find the range of the gradient across the image
+            let corners = [(0.0, 0.0), (width as f32, 0.0), (0.0, height as f32), (width as f32, height as f32)];
+            let along: Vec<f32> = corners.iter().map(|(x, y)| x * dx + y * dy).collect();
+            let low = along.iter().copied().fold(f32::MAX, f32::min);
+            let span = (along.iter().copied().fold(f32::MIN, f32::max) - low).max(1.0);
+            (0..width * height)
+                .map(|i| {
+                    let (x, y) = ((i % width) as f32, (i / width) as f32);
+                    mix(from, to, (x * dx + y * dy - low) / span)
+                })
+                .collect()
+        }
+        Style::PhotoLike => {
+            let (zenith, haze) = ([70.0, 120.0, 200.0], [190.0, 210.0, 230.0]);
+            let (dark, light) = (random_color(rng, 20.0, 90.0), random_color(rng, 90.0, 180.0));
+            let ground = fractal_noise(width, height, rng);
+            let hills = fractal_noise(width, 1, rng);
+            let horizon = rng.gen_range(0.35..0.65) * height as f32;
+            let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+            let radius = (cx * cx + cy * cy).sqrt();
+            (0..width * height)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    let edge = horizon + (hills[x] - 0.5) * height as f32 * 0.3;
+                    let color = if (y as f32) < edge {
+                        mix(zenith, haze, y as f32 / edge.max(1.0))
+                    } else {
+                        mix(dark, light, ground[i])
+                    };
+                    let distance = ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt() / radius;
+                    color.map(|c| c * (1.0 - 0.25 * distance * distance))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Makes an 8 bit RGB image that looks like an ordinary picture, to carry a message when
+/// there is no cover image at hand. Every style gets sensor-like grain, so the least
+/// significant bits look as random as they would in a photograph.
+pub fn generate(width: u32, height: u32, style: Style, rng: &mut impl Rng) -> Result<Png> {
+    if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+        return Err(Error::from(format!("width and height must be between 1 and {}", MAX_SIDE)));
+    }
+    let sigma = match style {
+        Style::Noise => 6.0,
+        Style::Gradient => 2.0,
+        Style::PhotoLike => 3.0,
+    };
+    let colors = base_colors(width as usize, height as usize, style, rng);
+    let samples = colors
+        .iter()
+        .flat_map(|color| *color)
+        .map(|c| (c + grain(rng, sigma)).round().clamp(0.0, 255.0) as u16)
+        .collect();
+    PngBuilder::new(width, height, 2, 8)
+        .samples(samples)
+        .gamma(1.0 / 2.2)
+        .physical(2835, 2835, true)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const STYLES: [Style; 3] = [Style::Noise, Style::Gradient, Style::PhotoLike];
+
+    #[test]
+    fn test_generate_styles() {
+        for style in STYLES {
+            let png = generate(40, 30, style, &mut StdRng::seed_from_u64(7)).unwrap();
+            let parsed = Png::try_from(&png.as_bytes()[..]).unwrap();
+            let image = pixels::decode(&parsed).unwrap();
+            assert_eq!((image.width(), image.height(), image.channels()), (40, 30, 3));
+        }
+    }
+
+    #[test]
+    fn test_generate_is_reproducible() {
+        let a = generate(16, 16, Style::PhotoLike, &mut StdRng::seed_from_u64(1)).unwrap();
+        let b = generate(16, 16, Style::PhotoLike, &mut StdRng::seed_from_u64(1)).unwrap();
+        let c = generate(16, 16, Style::PhotoLike, &mut StdRng::seed_from_u64(2)).unwrap();
+        assert_eq!(a.as_bytes(), b.as_bytes());
+        assert_ne!(a.as_bytes(), c.as_bytes());
+    }
+
+    #[test]
+    fn test_generate_lsbs_look_random() {
+        for style in STYLES {
+            let png = generate(64, 64, style, &mut StdRng::seed_from_u64(3)).unwrap();
+            let samples = pixels::decode(&png).unwrap().samples;
+            let ones = samples.iter().filter(|&&sample| sample & 1 == 1).count() as f64 / samples.len() as f64;
+            assert!((0.45..0.55).contains(&ones), "{:?}: {}", style, ones);
+        }
+    }
+
+    #[test]
+    fn test_generate_rejects_bad_sizes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(generate(0, 10, Style::Noise, &mut rng).is_err());
+        assert!(generate(10, MAX_SIDE + 1, Style::Noise, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+/// Bytes shown on each line of a dump
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Formats bytes the way `hexdump -C` does: offset, hex bytes split in two groups
+/// of eight, then the printable ASCII characters. `base` is the offset of the first byte.
+pub fn hexdump(bytes: &[u8], base: usize) -> Vec<String> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let mut hex = String::new();
+            for i in 0..BYTES_PER_LINE {
+                if i == BYTES_PER_LINE / 2 {
+                    hex.push(' ');
+                }
+                match chunk.get(i) {
+                    Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+                    None => hex.push_str("   "),
+                }
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+                .collect();
+            format!("{:08x}  {} |{}|", base + line * BYTES_PER_LINE, hex, ascii)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump_full_line() {
+        let lines = hexdump(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR", 0);
+        assert_eq!(
+            lines,
+            vec!["00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|"]
+        );
+    }
+
+    #[test]
+    fn test_hexdump_partial_line_and_base() {
+        let lines = hexdump(b"ab", 0x20);
+        assert_eq!(
+            lines,
+            vec!["00000020  61 62                                             |ab|"]
+        );
+    }
+
+    #[test]
+    fn test_hexdump_multiple_lines() {
+        let lines = hexdump(&[0; 33], 8);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with("00000028  00 "));
+    }
+}
@@ -0,0 +1,45 @@
+//! Reading, editing and hiding data in PNG files.
+//!
+//! A [`Png`] is a list of [`Chunk`]s plus any bytes after the final IEND chunk. Parse one
+//! with [`Png::from_file`] or `Png::try_from(&bytes[..])`, change it through its methods and
+//! write it back with [`Png::write_file`] or [`Png::as_bytes`]. The [`message`] module hides
+//! and recovers messages; the other modules inspect, clean up and compare files.
+//!
+//! ```
+//! use pngme::message::{self, Location};
+//! use pngme::{ChunkType, Png};
+//! use std::str::FromStr;
+//!
+//! let mut png = Png::from_file("dice.png")?;
+//! let location = Location::Chunk(ChunkType::from_str("ruSt")?);
+//! message::encode(&mut png, &location, b"hello")?;
+//! assert_eq!(message::decode(&png, &location)?, b"hello");
+//! # Ok::<(), pngme::Error>(())
+//! ```
+
+pub mod atomic;
+pub mod builder;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_type;
+pub mod describe;
+pub mod diff;
+pub mod generate;
+pub mod message;
+pub mod order;
+pub mod patch;
+pub mod pixels;
+pub mod png;
+pub mod registry;
+pub mod scan;
+pub mod strip;
+pub mod zip;
+
+pub use builder::PngBuilder;
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use png::Png;
+
+/// Errors are reported as messages meant for the person running the tool
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,22 +1,103 @@
 mod args;
-mod chunk;
-mod chunk_type;
+mod batch;
 mod commands;
-mod png;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+mod hexdump;
+mod output;
 
 use clap::Parser;
+use pngme::Result;
+use std::io::Write;
+
+/// Runs a command that works on a fixed set of files rather than a batch, printing its output
+fn single(f: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<()> {
+    let mut out = vec![];
+    let result = f(&mut out);
+    std::io::stdout().write_all(&out)?;
+    result
+}
 
 fn main() -> Result<()> {
     // todo!()
     let cli = args::Cli::parse();
+    let (batch, format) = (&cli.batch, cli.format);
 
-    match cli.command {
-        args::Commands::Encode(args) => {return commands::encode(args)},
-        args::Commands::Decode(args) => {return commands::decode(args)},
-        args::Commands::Remove(args)=> {return commands::remove(args)},
-        args::Commands::Print(args) => {return commands::print_chunks(args)},
-    };
+    match &cli.command {
+        args::Commands::Encode(args) => {
+            commands::check_output(args.output.output.as_deref(), &args.path, batch)?;
+            let location = commands::encode_location(args)?;
+            let message = commands::read_message(args)?;
+            batch::run(&args.path, batch, format, |path, out| commands::encode(args, location.as_ref(), &message, path, out))
+        }
+        args::Commands::Decode(args) => {
+            batch::run(&args.path, batch, format, |path, out| commands::decode(args, format, path, out))
+        }
+        args::Commands::Remove(args)=> {
+            commands::check_output(args.output.output.as_deref(), &args.path, batch)?;
+            batch::run(&args.path, batch, format, |path, out| commands::remove(args, path, out))
+        }
+        args::Commands::Print(args) => {
+            batch::run(&args.path, batch, format, |path, out| commands::print_chunks(args, format, path, out))
+        }
+        args::Commands::Validate(args) => {
+            batch::run(&args.path, batch, format, |path, out| commands::validate(format, path, out))
+        }
+        args::Commands::Polyglot(args) => {
+            commands::check_output(Some(&args.output), &args.inputs, batch)?;
+            batch::run(&args.inputs, batch, format, |path, out| commands::polyglot(args, path, out))
+        }
+        args::Commands::ExtractZip(args) => {
+            commands::check_output(Some(&args.output), &args.inputs, batch)?;
+            batch::run(&args.inputs, batch, format, |path, out| commands::extract_zip(args, path, out))
+        }
+        args::Commands::Scan(args) => {
+            batch::run(&args.paths, batch, format, |path, out| commands::scan(format, path, out))
+        }
+        args::Commands::Capacity(args) => {
+            batch::run(&args.paths, batch, format, |path, out| commands::capacity(format, path, out))
+        }
+        args::Commands::Strip(args) => {
+            commands::check_output(args.output.output.as_deref(), &args.path, batch)?;
+            batch::run(&args.path, batch, format, |path, out| commands::strip(args, path, out))
+        }
+        args::Commands::Dump(args) => {
+            batch::run(&args.path, batch, format, |path, out| commands::dump(args, format, path, out))
+        }
+        args::Commands::Extract(args) => {
+            commands::check_output(Some(&args.output), &args.path, batch)?;
+            batch::run(&args.path, batch, format, |path, out| commands::extract(args, path, out))
+        }
+        args::Commands::Insert(args) => {
+            commands::check_output(args.output.output.as_deref(), &args.path, batch)?;
+            let data = commands::read_chunk_data(args)?;
+            batch::run(&args.path, batch, format, |path, out| commands::insert(args, &data, path, out))
+        }
+        args::Commands::Reorder(args) => {
+            commands::check_output(args.output.output.as_deref(), &args.path, batch)?;
+            batch::run(&args.path, batch, format, |path, out| commands::reorder(args, path, out))
+        }
+        args::Commands::RepairCrc(args) => {
+            commands::check_output(args.output.output.as_deref(), &args.path, batch)?;
+            batch::run(&args.path, batch, format, |path, out| commands::repair_crc(args, path, out))
+        }
+        args::Commands::Diff(args) => {
+            single(|out| commands::diff(args, format, out))
+        }
+        args::Commands::Patch(args) => match &args.command {
+            args::PatchCommands::Apply(args) => {
+                single(|out| commands::patch_apply(args, out))
+            }
+        },
+        args::Commands::Generate(args) => {
+            single(|out| commands::generate(args, out))
+        }
+        args::Commands::DumpJson(args) => {
+            batch::run(&args.path, batch, format, |path, out| commands::dump_json(args, path, out))
+        }
+        args::Commands::BuildJson(args) => {
+            single(|out| commands::build_json(args, out))
+        }
+        args::Commands::Types(args) => {
+            single(|out| commands::types(args, format, out))
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Where in a PNG a message is hidden
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// The data of the first chunk of this type, added in front of IEND
+    Chunk(ChunkType),
+    /// The bytes after IEND, which decoders ignore
+    Trailer,
+}
+
+/// Checks that a chunk type can carry a message without making the image unreadable or
+/// clashing with a chunk that means something else. Types that work but are easily lost
+/// come back as warnings.
+pub fn check_chunk_type(chunk_type: &ChunkType) -> Result<Vec<String>> {
+    if !chunk_type.is_reserved_bit_valid() {
+        return Err(Error::from(format!(
+            "`{}` has the reserved bit set (lowercase third letter), decoders may reject the image",
+            chunk_type
+        )));
+    }
+    if chunk_type.is_critical() {
+        return Err(Error::from(format!(
+            "`{}` is critical (uppercase first letter), decoders refuse images with unknown critical chunks",
+            chunk_type
+        )));
+    }
+    let mut warnings = vec![];
+    match chunk_type.info() {
+        Some(info) if chunk_type.is_public() => {
+            return Err(Error::from(format!("`{}` is the registered {} chunk, use a private type", chunk_type, info.description)))
+        }
+        Some(info) => warnings.push(format!("`{}` is also used by {} for {}", chunk_type, info.source, info.description)),
+        None if chunk_type.is_public() => {
+            warnings.push(format!("`{}` is public (uppercase second letter), which is reserved for registered types", chunk_type))
+        }
+        None => {}
+    }
+    if !chunk_type.is_safe_to_copy() {
+        warnings.push(format!(
+            "`{}` is not safe to copy (uppercase last letter), editors that change the image will drop it",
+            chunk_type
+        ));
+    }
+    Ok(warnings)
+}
+
+/// Hides `message` in `png`. A chunk is added in front of IEND; a trailer replaces any
+/// data that was already after IEND. Chunk types that `check_chunk_type` refuses are an
+/// error, its warnings are not.
+pub fn encode(png: &mut Png, location: &Location, message: &[u8]) -> Result<()> {
+    match location {
+        Location::Chunk(chunk_type) => {
+            check_chunk_type(chunk_type)?;
+            let chunk = Chunk::try_new(chunk_type.clone(), message.to_vec())
+                .map_err(|err| Error::from(format!("the message does not fit in a chunk: {}", err)))?;
+            png.append_chunk(chunk);
+        }
+        Location::Trailer => png.set_trailer(message.to_vec()),
+    }
+    Ok(())
+}
+
+/// Finds the message hidden in `png`
+pub fn decode<'a>(png: &'a Png, location: &Location) -> Result<&'a [u8]> {
+    match location {
+        Location::Chunk(chunk_type) => png
+            .chunk_by_type(chunk_type)
+            .map(Chunk::data)
+            .ok_or_else(|| Error::from(format!("no `{}` chunk", chunk_type))),
+        Location::Trailer if png.trailer().is_empty() => Err(Error::from("no data after IEND")),
+        Location::Trailer => Ok(png.trailer()),
+    }
+}
+
+/// Deletes the message hidden in `png` and returns it
+pub fn remove(png: &mut Png, location: &Location) -> Result<Vec<u8>> {
+    match location {
+        Location::Chunk(chunk_type) => Ok(png.remove_chunk(chunk_type)?.data().to_vec()),
+        Location::Trailer => {
+            let message = decode(png, location)?.to_vec();
+            png.set_trailer(vec![]);
+            Ok(message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let mut png = Png::try_from(DICE).unwrap();
+        let location = Location::Chunk(ChunkType::from_str("ruSt").unwrap());
+
+        encode(&mut png, &location, b"secret").unwrap();
+        assert_eq!(*png.chunks().last().unwrap().chunk_type(), ChunkType::IEND);
+        assert_eq!(decode(&png, &location).unwrap(), b"secret");
+        assert_eq!(remove(&mut png, &location).unwrap(), b"secret");
+        assert!(decode(&png, &location).is_err());
+        assert_eq!(png.as_bytes(), DICE);
+    }
+
+    #[test]
+    fn test_encode_refuses_unsafe_chunk_types() {
+        let mut png = Png::try_from(DICE).unwrap();
+        for name in ["IHDR", "tEXt", "RuSt", "rust"] {
+            let location = Location::Chunk(ChunkType::from_str(name).unwrap());
+            assert!(encode(&mut png, &location, b"secret").is_err());
+        }
+        assert_eq!(png.as_bytes(), DICE);
+
+        encode(&mut png, &Location::Chunk(ChunkType::from_str("ruST").unwrap()), b"secret").unwrap();
+    }
+
+    #[test]
+    fn test_trailer_round_trip() {
+        let mut png = Png::try_from(DICE).unwrap();
+
+        assert!(decode(&png, &Location::Trailer).is_err());
+        encode(&mut png, &Location::Trailer, b"secret").unwrap();
+        assert_eq!(decode(&png, &Location::Trailer).unwrap(), b"secret");
+        assert_eq!(remove(&mut png, &Location::Trailer).unwrap(), b"secret");
+        assert!(png.trailer().is_empty());
+    }
+
+    #[test]
+    fn test_check_chunk_type() {
+        let check = |name: &str| check_chunk_type(&ChunkType::from_str(name).unwrap());
+        assert!(check("ruSt").unwrap().is_empty());
+        assert!(check("rust").is_err());
+        assert!(check("RuSt").is_err());
+        assert!(check("tEXt").is_err());
+        assert_eq!(check("ruST").unwrap().len(), 1);
+        assert_eq!(check("rUSt").unwrap().len(), 1);
+        assert!(check("vpAg").unwrap()[0].contains("ImageMagick"));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::registry::{Placement, REGISTRY};
+
+/// Position of a chunk type in the sequence the spec requires, from its placement in the
+/// registry. Chunks that may go anywhere, and unregistered ones, get `None` and keep their
+/// place relative to the constrained chunk before them.
+fn rank(chunk: &Chunk) -> Option<u8> {
+    chunk.chunk_type().info().and_then(|info| info.placement.rank())
+}
+
+fn placement(chunk: &Chunk) -> Option<Placement> {
+    chunk.chunk_type().info().map(|info| info.placement)
+}
+
+/// Describes every way the chunks break the rules the registry records: the required chunks,
+/// IHDR first (after Apple's CgBI, if there is one), IEND last, PLTE before IDAT, consecutive
+/// IDAT chunks, the placement of ancillary chunks and how many chunks of each type there may
+/// be. Chunks without a rule (text, tIME, unregistered chunks) may appear anywhere in between.
+pub fn violations(chunks: &[Chunk]) -> Vec<String> {
+    let mut found = vec![];
+    let names: Vec<String> = chunks.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    if names.is_empty() {
+        return vec!["there are no chunks".to_string()];
+    }
+
+    for info in REGISTRY.iter().filter(|info| info.multiplicity.is_required()) {
+        if !chunks.iter().any(|chunk| chunk.chunk_type().bytes() == info.name.as_bytes()) {
+            found.push(format!("there is no {} chunk", info.name));
+        }
+    }
+    let leading = chunks.iter().take_while(|chunk| placement(chunk) == Some(Placement::BeforeIhdr)).count();
+    if chunks.get(leading).is_some_and(|chunk| *chunk.chunk_type() != ChunkType::IHDR) {
+        found.push(format!("the first chunk is `{}`, not IHDR", names[leading]));
+    }
+    let has_iend = chunks.iter().any(|chunk| *chunk.chunk_type() == ChunkType::IEND);
+    if has_iend && chunks.last().is_some_and(|chunk| *chunk.chunk_type() != ChunkType::IEND) {
+        found.push("the last chunk is not IEND".to_string());
+    }
+    let mut counted: Vec<&str> = vec![];
+    for chunk in chunks {
+        let Some(info) = chunk.chunk_type().info() else { continue };
+        if info.multiplicity.limit() != Some(1) || counted.contains(&info.name) {
+            continue;
+        }
+        counted.push(info.name);
+        let count = chunks.iter().filter(|other| other.chunk_type() == chunk.chunk_type()).count();
+        if count > 1 {
+            found.push(format!("there are {} {} chunks, at most one is allowed", count, info.name));
+        }
+    }
+
+    let idat: Vec<usize> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| *chunk.chunk_type() == ChunkType::IDAT)
+        .map(|(i, _)| i)
+        .collect();
+    match (idat.first(), idat.last()) {
+        (Some(first), Some(last)) if last - first + 1 != idat.len() => {
+            found.push(format!("IDAT chunks between index {} and {} are not consecutive", first, last));
+        }
+        _ => {}
+    }
+
+    // every constrained chunk must rank at least as high as the highest ranked one before it
+    let mut highest: Option<(u8, usize)> = None;
+    for (index, chunk) in chunks.iter().enumerate() {
+        let Some(rank) = rank(chunk) else { continue };
+        match highest {
+            Some((top, at)) if rank < top => found.push(format!(
+                "`{}` at index {} must come before `{}` at index {}",
+                names[index], index, names[at], at
+            )),
+            Some((top, _)) if rank == top => {}
+            _ => highest = Some((rank, index)),
+        }
+    }
+    found
+}
+
+/// The violations of `after` that `before` did not have, so an edit can be refused for
+/// making a file worse without refusing every edit to a file that was already out of order.
+/// Indices are left out of the comparison, since moving a chunk shifts them.
+pub fn added_violations(before: &[Chunk], after: &[Chunk]) -> Vec<String> {
+    let key = |violation: &String| violation.replace(|c: char| c.is_ascii_digit(), "");
+    let mut existing: Vec<String> = violations(before).iter().map(key).collect();
+    violations(after)
+        .into_iter()
+        .filter(|violation| match existing.iter().position(|old| *old == key(violation)) {
+            Some(at) => {
+                existing.swap_remove(at);
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
+/// Reorders the chunks into the sequence the spec recommends, moving as little as possible:
+/// constrained chunks are sorted by rank and every other chunk travels with the constrained
+/// chunk before it, or right after the IDAT run if that is where it was.
+pub fn normalize(png: &mut Png) {
+    let after_idat = Placement::AfterIdat.rank().unwrap_or_default();
+    // chunks in front of IHDR go after it
+    let mut last = Placement::First.rank().unwrap_or_default();
+    let ranks: Vec<u8> = png
+        .chunks()
+        .iter()
+        .map(|chunk| match rank(chunk) {
+            Some(rank) => {
+                last = rank;
+                rank
+            }
+            None => (last + 1).min(after_idat),
+        })
+        .collect();
+    png.sort_chunks_by_key(|index, _| ranks[index]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn png_of(types: &[&str]) -> Png {
+        let chunks = types
+            .iter()
+            .map(|name| Chunk::new(ChunkType::from_str(name).unwrap(), vec![]))
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    fn names(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_valid_order() {
+        let png = png_of(&["IHDR", "gAMA", "tEXt", "PLTE", "tRNS", "IDAT", "IDAT", "tIME", "IEND"]);
+        assert!(violations(png.chunks()).is_empty());
+    }
+
+    #[test]
+    fn test_added_violations() {
+        // gAMA is already after PLTE; moving tEXt does not make that worse
+        let before = png_of(&["IHDR", "PLTE", "gAMA", "tEXt", "IDAT", "IEND"]);
+        let mut after = png_of(&["IHDR", "PLTE", "gAMA", "tEXt", "IDAT", "IEND"]);
+        after.move_chunk(3, 1).unwrap();
+        assert_eq!(violations(after.chunks()).len(), 1);
+        assert!(added_violations(before.chunks(), after.chunks()).is_empty());
+
+        // moving gAMA back in front of PLTE repairs it
+        let mut repaired = png_of(&["IHDR", "PLTE", "gAMA", "tEXt", "IDAT", "IEND"]);
+        repaired.move_chunk(2, 1).unwrap();
+        assert!(violations(repaired.chunks()).is_empty());
+
+        // but moving IDAT in front of PLTE puts both PLTE and gAMA after it
+        let mut broken = png_of(&["IHDR", "PLTE", "gAMA", "tEXt", "IDAT", "IEND"]);
+        broken.move_chunk(4, 1).unwrap();
+        assert_eq!(added_violations(before.chunks(), broken.chunks()).len(), 2);
+    }
+
+    #[test]
+    fn test_violations() {
+        let png = png_of(&["IHDR", "PLTE", "gAMA", "IDAT", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(
+            violations(png.chunks()),
+            vec![
+                "IDAT chunks between index 3 and 5 are not consecutive",
+                "`gAMA` at index 2 must come before `PLTE` at index 1",
+            ]
+        );
+
+        let png = png_of(&["gAMA", "IHDR", "IDAT"]);
+        assert_eq!(
+            violations(png.chunks()),
+            vec![
+                "there is no IEND chunk",
+                "the first chunk is `gAMA`, not IHDR",
+                "`IHDR` at index 1 must come before `gAMA` at index 0",
+            ]
+        );
+
+        let png = png_of(&["IHDR", "tEXt", "IEND", "tIME"]);
+        assert_eq!(violations(png.chunks()), vec!["there is no IDAT chunk", "the last chunk is not IEND"]);
+    }
+
+    #[test]
+    fn test_cgbi_comes_before_ihdr() {
+        let mut png = png_of(&["CgBI", "IHDR", "IDAT", "IEND"]);
+        assert!(violations(png.chunks()).is_empty());
+        normalize(&mut png);
+        assert_eq!(names(&png), vec!["CgBI", "IHDR", "IDAT", "IEND"]);
+
+        let mut png = png_of(&["IHDR", "CgBI", "IDAT", "IEND"]);
+        assert_eq!(violations(png.chunks()), vec!["`CgBI` at index 1 must come before `IHDR` at index 0"]);
+        normalize(&mut png);
+        assert_eq!(names(&png), vec!["CgBI", "IHDR", "IDAT", "IEND"]);
+        assert!(violations(png.chunks()).is_empty());
+        let mut png = png_of(&["tEXt", "IHDR", "CgBI", "IDAT", "IEND"]);
+        normalize(&mut png);
+        assert_eq!(names(&png), vec!["CgBI", "IHDR", "tEXt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_violations_use_registry() {
+        let png = png_of(&["IHDR", "gAMA", "gAMA", "acTL", "IDAT", "fdAT", "tEXt", "tEXt", "IEND"]);
+        assert_eq!(violations(png.chunks()), vec!["there are 2 gAMA chunks, at most one is allowed"]);
+
+        let png = png_of(&["IHDR", "fdAT", "IDAT", "IEND"]);
+        assert_eq!(violations(png.chunks()), vec!["`IDAT` at index 2 must come before `fdAT` at index 1"]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut png = png_of(&["IHDR", "tEXt", "PLTE", "IDAT", "zzZz", "IDAT", "sRGB", "IEND", "tIME"]);
+        normalize(&mut png);
+
+        assert_eq!(names(&png), vec!["IHDR", "tEXt", "sRGB", "PLTE", "IDAT", "IDAT", "zzZz", "tIME", "IEND"]);
+        assert!(violations(png.chunks()).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_keeps_valid_order() {
+        let types = ["IHDR", "gAMA", "cHRM", "bKGD", "tIME", "IDAT", "IDAT", "tEXt", "IEND"];
+        let mut png = png_of(&types);
+        normalize(&mut png);
+        assert_eq!(names(&png), types);
+    }
+}
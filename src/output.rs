@@ -0,0 +1,276 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use pngme::capacity::{Capacity, Estimate};
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::describe;
+use pngme::diff::{Change, PixelComparison};
+use pngme::png::Png;
+use pngme::registry::ChunkInfo;
+use pngme::scan::{Finding, Report};
+use pngme::{Error, Result};
+
+/// The name and property flags of a chunk type
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkTypeSummary {
+    pub name: String,
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+}
+
+impl From<&ChunkType> for ChunkTypeSummary {
+    fn from(chunk_type: &ChunkType) -> Self {
+        ChunkTypeSummary {
+            name: chunk_type.to_string(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+        }
+    }
+}
+
+/// One chunk as listed by `print`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub index: usize,
+    pub offset: usize,
+    pub length: u32,
+    #[serde(rename = "type")]
+    pub chunk_type: ChunkTypeSummary,
+    pub stored_crc: u32,
+    pub computed_crc: u32,
+    pub summary: Option<String>,
+    /// What the registry says the chunk type is, `None` for unregistered types
+    pub description: Option<String>,
+}
+
+impl ChunkSummary {
+    pub fn new(index: usize, offset: usize, chunk: &Chunk) -> ChunkSummary {
+        ChunkSummary {
+            index,
+            offset,
+            length: chunk.length(),
+            chunk_type: chunk.chunk_type().into(),
+            stored_crc: chunk.stored_crc(),
+            computed_crc: chunk.crc(),
+            summary: describe::summarize(chunk),
+            description: chunk.chunk_type().info().map(|info| info.description.to_string()),
+        }
+    }
+}
+
+/// A whole file as listed by `print`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PngSummary {
+    pub path: String,
+    pub chunks: Vec<ChunkSummary>,
+    pub trailer_length: usize,
+}
+
+impl PngSummary {
+    /// Summarizes the chunks of `png` that pass `filter`, keeping their original indices
+    pub fn new(path: &Path, png: &Png, filter: impl Fn(&Chunk) -> bool) -> PngSummary {
+        let chunks = png
+            .chunks()
+            .iter()
+            .zip(png.offsets())
+            .enumerate()
+            .filter(|(_, (chunk, _))| filter(chunk))
+            .map(|(index, (chunk, offset))| ChunkSummary::new(index, offset, chunk))
+            .collect();
+        PngSummary { path: path.display().to_string(), chunks, trailer_length: png.trailer().len() }
+    }
+}
+
+/// The message found by `decode`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DecodeOutput {
+    pub path: String,
+    pub method: String,
+    pub chunk_type: Option<String>,
+    pub message: String,
+}
+
+/// The outcome of `validate`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidateOutput {
+    pub path: String,
+    pub valid: bool,
+    pub chunks: usize,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// The findings of `scan` for one file
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ScanOutput {
+    pub path: String,
+    pub risk: u32,
+    pub level: String,
+    pub chi_square: Option<f64>,
+    pub rs_estimate: Option<f64>,
+    pub pixel_error: Option<String>,
+    pub findings: Vec<Finding>,
+}
+
+impl ScanOutput {
+    pub fn new(path: &Path, report: &Report) -> ScanOutput {
+        ScanOutput {
+            path: path.display().to_string(),
+            risk: report.risk(),
+            level: report.level().to_string(),
+            chi_square: report.chi_square,
+            rs_estimate: report.rs_estimate,
+            pixel_error: report.pixel_error.clone(),
+            findings: report.findings.clone(),
+        }
+    }
+}
+
+/// The differences found by `diff`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffOutput {
+    pub old: String,
+    pub new: String,
+    pub changes: Vec<Change>,
+    pub pixels: Option<PixelComparison>,
+}
+
+/// What one method can carry, as reported by `capacity`: a number of bytes, `unlimited`, or
+/// the reason the method does not apply
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EstimateOutput {
+    pub method: String,
+    pub bytes: Option<u64>,
+    pub unlimited: bool,
+    pub not_applicable: Option<String>,
+}
+
+impl From<&Estimate> for EstimateOutput {
+    fn from(estimate: &Estimate) -> Self {
+        let (bytes, unlimited, not_applicable) = match estimate.capacity {
+            Capacity::Bytes(bytes) => (Some(bytes), false, None),
+            Capacity::Unlimited => (None, true, None),
+            Capacity::NotApplicable(reason) => (None, false, Some(reason.to_string())),
+        };
+        EstimateOutput { method: estimate.method.clone(), bytes, unlimited, not_applicable }
+    }
+}
+
+/// The raw capacity of every method for one file, from `capacity`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CapacityOutput {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: u8,
+    pub bit_depth: u8,
+    pub estimates: Vec<EstimateOutput>,
+}
+
+/// One chunk as shown by `dump`, with the selected part of its data in hex
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DumpOutput {
+    pub index: usize,
+    pub offset: usize,
+    pub length: u32,
+    #[serde(rename = "type")]
+    pub chunk_type: ChunkTypeSummary,
+    pub stored_crc: u32,
+    pub computed_crc: u32,
+    pub data_start: usize,
+    pub data_end: usize,
+    pub data: String,
+}
+
+/// The chunks `dump` selected in one file
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DumpFileOutput {
+    pub path: String,
+    pub chunks: Vec<DumpOutput>,
+}
+
+/// A chunk type as listed by `types`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TypeOutput {
+    #[serde(flatten)]
+    pub chunk_type: ChunkTypeSummary,
+    /// The registry entry, `None` for unregistered types
+    pub info: Option<ChunkInfo>,
+}
+
+impl From<&ChunkType> for TypeOutput {
+    fn from(chunk_type: &ChunkType) -> Self {
+        TypeOutput { chunk_type: chunk_type.into(), info: chunk_type.info().cloned() }
+    }
+}
+
+/// Writes `value` to `out` as pretty printed JSON
+pub fn write_json<T: Serialize>(out: &mut Vec<u8>, value: &T) -> Result<()> {
+    serde_json::to_writer_pretty(&mut *out, value)
+        .map_err(|err| Error::from(format!("failed to serialize output: {}", err)))?;
+    out.push(b'\n');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    #[test]
+    fn test_chunk_type_summary_json() {
+        let summary = ChunkTypeSummary::from(&ChunkType::from_str("ruSt").unwrap());
+        let value = serde_json::to_value(summary).unwrap();
+        assert_eq!(
+            value,
+            json!({"name": "ruSt", "critical": false, "public": false, "reserved_bit_valid": true, "safe_to_copy": true})
+        );
+    }
+
+    #[test]
+    fn test_type_output_json() {
+        let value = serde_json::to_value(TypeOutput::from(&ChunkType::from_str("gAMA").unwrap())).unwrap();
+        assert_eq!(value["name"], "gAMA");
+        assert_eq!(value["info"]["multiplicity"], "at_most_one");
+        assert_eq!(value["info"]["placement"], "before_plte");
+
+        let value = serde_json::to_value(TypeOutput::from(&ChunkType::from_str("ruSt").unwrap())).unwrap();
+        assert!(value["info"].is_null());
+    }
+
+    #[test]
+    fn test_estimate_output_json() {
+        let estimate = |capacity| EstimateOutput::from(&Estimate { method: "lsb-1".to_string(), capacity });
+        assert_eq!(
+            serde_json::to_value(estimate(Capacity::Bytes(10))).unwrap(),
+            json!({"method": "lsb-1", "bytes": 10, "unlimited": false, "not_applicable": null})
+        );
+        assert_eq!(estimate(Capacity::Unlimited).bytes, None);
+        assert!(estimate(Capacity::Unlimited).unlimited);
+        assert_eq!(estimate(Capacity::NotApplicable("no PLTE chunk")).not_applicable.as_deref(), Some("no PLTE chunk"));
+    }
+
+    #[test]
+    fn test_png_summary_json() {
+        let png = Png::try_from(DICE).unwrap();
+        let summary = PngSummary::new(Path::new("dice.png"), &png, |c| c.chunk_type().to_string() == "IHDR");
+        let value = serde_json::to_value(summary).unwrap();
+
+        assert_eq!(value["path"], "dice.png");
+        assert_eq!(value["trailer_length"], 0);
+        assert_eq!(value["chunks"][0]["index"], 0);
+        assert_eq!(value["chunks"][0]["offset"], 8);
+        assert_eq!(value["chunks"][0]["type"]["name"], "IHDR");
+        assert_eq!(value["chunks"][0]["summary"], "420x315, 8-bit RGBA, non-interlaced");
+        assert_eq!(value["chunks"].as_array().unwrap().len(), 1);
+    }
+}
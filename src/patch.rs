@@ -0,0 +1,286 @@
+use std::collections::{HashMap, HashSet};
+
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::diff;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// First bytes of every patch file
+pub const MAGIC: &[u8; 8] = b"PNGPATCH";
+
+/// Version of the patch format written by `Patch::to_bytes`
+pub const VERSION: u8 = 1;
+
+const OP_REMOVE: u8 = 1;
+const OP_INSERT: u8 = 2;
+const OP_REPLACE: u8 = 3;
+const OP_TRAILER: u8 = 4;
+
+/// One edit of the chunk list. Ops apply in order, each one to the list left by the last,
+/// so indices refer to positions at the time the op runs.
+#[derive(Debug)]
+pub enum Op {
+    Remove { index: usize },
+    Insert { index: usize, chunk: Chunk },
+    Replace { index: usize, chunk: Chunk },
+    Trailer { data: Vec<u8> },
+}
+
+/// The edits that turn one PNG file into another, bound to the exact file they were made
+/// from by its SHA-256 hash. The hash of the expected result is kept too, so a patch that
+/// applies cleanly is known to rebuild the file byte for byte.
+///
+/// Encoded as the magic bytes, a version byte, both hashes, a big endian `u32` op count and
+/// the ops: a tag byte followed by the index (`u32`), and for inserts and replacements the
+/// chunk type and length prefixed data. Trailer ops carry only length prefixed data.
+#[derive(Debug)]
+pub struct Patch {
+    pub base: [u8; 32],
+    pub result: [u8; 32],
+    pub ops: Vec<Op>,
+}
+
+/// SHA-256 of a whole file
+pub fn hash(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+fn hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn copy(chunk: &Chunk) -> Chunk {
+    Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec())
+}
+
+impl Patch {
+    /// Works out the ops that turn `a` into `b`. Chunks that kept their place stay where
+    /// they are (replaced when their contents changed); every other chunk of `a` is removed
+    /// and every other chunk of `b` inserted, so moves travel as a remove and an insert.
+    pub fn between(a: &Png, b: &Png) -> Patch {
+        let kept: HashMap<usize, usize> = diff::align(a, b)
+            .into_iter()
+            .filter(|(_, _, kept)| *kept)
+            .map(|(from, to, _)| (to, from))
+            .collect();
+        let kept_from: HashSet<usize> = kept.values().copied().collect();
+
+        let mut ops = vec![];
+        // remove from the back so the indices of the chunks still to go stay valid
+        for index in (0..a.chunks().len()).rev() {
+            if !kept_from.contains(&index) {
+                ops.push(Op::Remove { index });
+            }
+        }
+        // what is left is the kept chunks in order, so filling in `b` front to back
+        // means every op's index is the chunk's final position
+        for (index, chunk) in b.chunks().iter().enumerate() {
+            match kept.get(&index) {
+                Some(from) if a.chunks()[*from].as_bytes() == chunk.as_bytes() => {}
+                Some(_) => ops.push(Op::Replace { index, chunk: copy(chunk) }),
+                None => ops.push(Op::Insert { index, chunk: copy(chunk) }),
+            }
+        }
+        if a.trailer() != b.trailer() {
+            ops.push(Op::Trailer { data: b.trailer().to_vec() });
+        }
+        Patch { base: hash(&a.as_bytes()), result: hash(&b.as_bytes()), ops }
+    }
+
+    /// Applies the ops to `png`, refusing to touch it unless it is the file the patch was
+    /// made from. The ops are applied to a copy that replaces `png` only once it matches the
+    /// expected result, so a failed patch leaves `png` as it was.
+    pub fn apply(&self, png: &mut Png) -> Result<()> {
+        let found = hash(&png.as_bytes());
+        if found != self.base {
+            return Err(Error::from(format!(
+                "patch was made for a different file: expected sha256 {}, found {}",
+                hex(&self.base),
+                hex(&found)
+            )));
+        }
+        let mut patched = png.clone();
+        for op in &self.ops {
+            match op {
+                Op::Remove { index } => {
+                    patched.remove_at(*index)?;
+                }
+                Op::Insert { index, chunk } => patched.insert_chunk(*index, copy(chunk))?,
+                Op::Replace { index, chunk } => {
+                    patched.replace_at(*index, copy(chunk))?;
+                }
+                Op::Trailer { data } => patched.set_trailer(data.clone()),
+            }
+        }
+        if hash(&patched.as_bytes()) != self.result {
+            return Err(Error::from("patched file does not match the expected result"));
+        }
+        *png = patched;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend(self.base);
+        bytes.extend(self.result);
+        bytes.extend((self.ops.len() as u32).to_be_bytes());
+        for op in &self.ops {
+            match op {
+                Op::Remove { index } => {
+                    bytes.push(OP_REMOVE);
+                    bytes.extend((*index as u32).to_be_bytes());
+                }
+                Op::Insert { index, chunk } | Op::Replace { index, chunk } => {
+                    bytes.push(if matches!(op, Op::Insert { .. }) { OP_INSERT } else { OP_REPLACE });
+                    bytes.extend((*index as u32).to_be_bytes());
+                    bytes.extend(chunk.chunk_type().bytes());
+                    bytes.extend(chunk.length().to_be_bytes());
+                    bytes.extend(chunk.data());
+                }
+                Op::Trailer { data } => {
+                    bytes.push(OP_TRAILER);
+                    bytes.extend((data.len() as u32).to_be_bytes());
+                    bytes.extend(data);
+                }
+            }
+        }
+        bytes
+    }
+}
+
+/// Reads fields off the front of a patch, failing cleanly when it is cut short
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(Error::from("patch is truncated"));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<usize> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn hash(&mut self) -> Result<[u8; 32]> {
+        Ok(self.take(32)?.try_into().unwrap())
+    }
+
+    fn chunk(&mut self) -> Result<Chunk> {
+        let chunk_type: [u8; 4] = self.take(4)?.try_into().unwrap();
+        let chunk_type = ChunkType::try_from(chunk_type).map_err(|_| Error::from("patch holds an invalid chunk type"))?;
+        let length = self.u32()?;
+        Chunk::try_new(chunk_type, self.take(length)?.to_vec())
+    }
+}
+
+impl TryFrom<&[u8]> for Patch {
+    type Error = Error;
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(Error::from("not a pngme patch"));
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(Error::from(format!("unsupported patch version {}", version)));
+        }
+        let (base, result) = (reader.hash()?, reader.hash()?);
+        let count = reader.u32()?;
+        let mut ops = vec![];
+        for _ in 0..count {
+            let op = match reader.take(1)?[0] {
+                OP_REMOVE => Op::Remove { index: reader.u32()? },
+                OP_INSERT => Op::Insert { index: reader.u32()?, chunk: reader.chunk()? },
+                OP_REPLACE => Op::Replace { index: reader.u32()?, chunk: reader.chunk()? },
+                OP_TRAILER => {
+                    let length = reader.u32()?;
+                    Op::Trailer { data: reader.take(length)?.to_vec() }
+                }
+                tag => return Err(Error::from(format!("unknown patch op {}", tag))),
+            };
+            ops.push(op);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(Error::from("unexpected data after the last patch op"));
+        }
+        Ok(Patch { base, result, ops })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn edited() -> Png {
+        let mut png = Png::try_from(DICE).unwrap();
+        png.remove_chunk(&ChunkType::from_str("tIME").unwrap()).unwrap();
+        png.insert_chunk(1, chunk("ruSt", b"hidden")).unwrap();
+        png.move_chunk(2, 4).unwrap();
+        png.replace_at(3, chunk("bKGD", &[0, 255, 0, 255, 0, 255])).unwrap();
+        png.set_trailer(b"after".to_vec());
+        png
+    }
+
+    #[test]
+    fn test_patch_round_trip() {
+        let (a, b) = (Png::try_from(DICE).unwrap(), edited());
+        let bytes = Patch::between(&a, &b).to_bytes();
+        let patch = Patch::try_from(&bytes[..]).unwrap();
+
+        let mut patched = Png::try_from(DICE).unwrap();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched.as_bytes(), b.as_bytes());
+    }
+
+    #[test]
+    fn test_patch_is_small() {
+        let (a, b) = (Png::try_from(DICE).unwrap(), edited());
+        let bytes = Patch::between(&a, &b).to_bytes();
+        assert!(bytes.len() < 200, "{} bytes", bytes.len());
+    }
+
+    #[test]
+    fn test_patch_rejects_other_base() {
+        let patch = Patch::between(&Png::try_from(DICE).unwrap(), &edited());
+        let mut other = edited();
+        assert!(patch.apply(&mut other).is_err());
+    }
+
+    #[test]
+    fn test_failed_patch_leaves_png_untouched() {
+        let mut patch = Patch::between(&Png::try_from(DICE).unwrap(), &edited());
+        patch.result[0] ^= 1;
+        let mut png = Png::try_from(DICE).unwrap();
+        assert!(patch.apply(&mut png).is_err());
+        assert_eq!(png.as_bytes(), DICE);
+
+        let mut patch = Patch::between(&Png::try_from(DICE).unwrap(), &edited());
+        patch.ops.push(Op::Remove { index: 100 });
+        assert!(patch.apply(&mut png).is_err());
+        assert_eq!(png.as_bytes(), DICE);
+    }
+
+    #[test]
+    fn test_patch_rejects_bad_bytes() {
+        let bytes = Patch::between(&Png::try_from(DICE).unwrap(), &edited()).to_bytes();
+        assert!(Patch::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Patch::try_from(&b"PNGPATCX"[..]).is_err());
+    }
+}
@@ -0,0 +1,520 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// Starting column, starting row, column step and row step of the seven Adam7 passes
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The decoded fields of an IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub interlaced: bool,
+}
+
+impl Header {
+    pub fn from_chunk(chunk: &Chunk) -> Result<Header> {
+        let data = chunk.data();
+        if chunk.chunk_type().to_string() != "IHDR" || data.len() != 13 {
+            return Err(Error::from("not a valid IHDR chunk"));
+        }
+        let header = Header {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: data[9],
+            interlaced: data[12] == 1,
+        };
+        let depth_ok = match header.color_type {
+            0 => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+            3 => [1, 2, 4, 8].contains(&header.bit_depth),
+            2 | 4 | 6 => [8, 16].contains(&header.bit_depth),
+            _ => false,
+        };
+        if !depth_ok {
+            return Err(Error::from(format!(
+                "unsupported color type {} with bit depth {}",
+                header.color_type, header.bit_depth
+            )));
+        }
+        if header.width == 0 || header.height == 0 {
+            return Err(Error::from("image has no pixels"));
+        }
+        Ok(header)
+    }
+
+    /// The IHDR chunk describing this header, checked the same way `from_chunk` checks
+    /// a parsed one
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = vec![];
+        data.extend(self.width.to_be_bytes());
+        data.extend(self.height.to_be_bytes());
+        data.extend([self.bit_depth, self.color_type, 0, 0, self.interlaced as u8]);
+        let chunk = Chunk::new("IHDR".parse()?, data);
+        Header::from_chunk(&chunk)?;
+        Ok(chunk)
+    }
+
+    /// Reads the header from the first chunk of a PNG
+    pub fn from_png(png: &Png) -> Result<Header> {
+        let first = png.chunks().first().ok_or_else(|| Error::from("png has no chunks"))?;
+        Header::from_chunk(first)
+    }
+
+    /// Number of samples that make up one pixel
+    pub fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// True when the last channel of every pixel is alpha
+    pub fn has_alpha(&self) -> bool {
+        self.color_type == 4 || self.color_type == 6
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in one unfiltered scanline of `width` pixels
+    pub fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Starting column, starting row, column step and row step of every pass
+    fn passes(&self) -> Vec<(usize, usize, usize, usize)> {
+        if self.interlaced {
+            ADAM7.to_vec()
+        } else {
+            vec![(0, 0, 1, 1)]
+        }
+    }
+
+    /// Width and height of a pass, or `None` for the empty passes of small interlaced images
+    fn pass_size(&self, (x0, y0, dx, dy): (usize, usize, usize, usize)) -> Option<(usize, usize)> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if x0 >= width || y0 >= height {
+            return None;
+        }
+        Some(((width - x0).div_ceil(dx), (height - y0).div_ceil(dy)))
+    }
+
+    /// Bytes of inflated image data the header calls for: every scanline of every pass with
+    /// its filter byte. Fails when the size does not even fit in a `usize`, as a hostile
+    /// header can ask for.
+    pub fn raw_size(&self) -> Result<usize> {
+        let too_large = || Error::from(format!("a {}x{} image is too large to decode", self.width, self.height));
+        let mut size = 0usize;
+        for pass in self.passes() {
+            let Some((pass_width, pass_height)) = self.pass_size(pass) else { continue };
+            let row_bytes = pass_width.checked_mul(self.bits_per_pixel()).ok_or_else(too_large)?.div_ceil(8);
+            size = (row_bytes + 1)
+                .checked_mul(pass_height)
+                .and_then(|pass_size| pass_size.checked_add(size))
+                .ok_or_else(too_large)?;
+        }
+        Ok(size)
+    }
+}
+
+/// Samples of an image in raster order, `channels()` samples per pixel.
+/// Sixteen bit images keep their full sample values.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub header: Header,
+    pub samples: Vec<u16>,
+}
+
+impl Image {
+    pub fn width(&self) -> usize {
+        self.header.width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.header.height as usize
+    }
+
+    pub fn channels(&self) -> usize {
+        self.header.channels()
+    }
+
+    /// The samples of one channel of one row
+    pub fn row_channel(&self, row: usize, channel: usize) -> impl Iterator<Item = u16> + '_ {
+        let stride = self.width() * self.channels();
+        self.samples[row * stride..(row + 1) * stride]
+            .iter()
+            .skip(channel)
+            .step_by(self.channels())
+            .copied()
+    }
+}
+
+/// Concatenates and inflates the IDAT chunks of a PNG, failing if they inflate to more than
+/// `limit` bytes rather than reading a decompression bomb to the end
+pub fn inflate_idat(png: &Png, limit: usize) -> Result<Vec<u8>> {
+    let compressed: Vec<u8> = png
+        .chunks_by_type(&ChunkType::IDAT)
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.is_empty() {
+        return Err(Error::from("png has no IDAT chunks"));
+    }
+    let mut raw = vec![];
+    ZlibDecoder::new(&compressed[..])
+        .take(limit as u64 + 1)
+        .read_to_end(&mut raw)
+        .map_err(|err| Error::from(format!("failed to inflate image data: {}", err)))?;
+    if raw.len() > limit {
+        return Err(Error::from(format!("image data inflates to more than the {} bytes the header allows", limit)));
+    }
+    Ok(raw)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses the per scanline filters in place, returning the rows without their filter bytes
+fn unfilter(data: &[u8], rows: usize, row_bytes: usize, bpp: usize) -> Result<Vec<u8>> {
+    let mut out = vec![0u8; rows * row_bytes];
+    for row in 0..rows {
+        let line = &data[row * (row_bytes + 1)..(row + 1) * (row_bytes + 1)];
+        let (filter, line) = (line[0], &line[1..]);
+        for i in 0..row_bytes {
+            let a = if i >= bpp { out[row * row_bytes + i - bpp] } else { 0 };
+            let b = if row > 0 { out[(row - 1) * row_bytes + i] } else { 0 };
+            let c = if row > 0 && i >= bpp { out[(row - 1) * row_bytes + i - bpp] } else { 0 };
+            out[row * row_bytes + i] = match filter {
+                0 => line[i],
+                1 => line[i].wrapping_add(a),
+                2 => line[i].wrapping_add(b),
+                3 => line[i].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => line[i].wrapping_add(paeth(a, b, c)),
+                _ => return Err(Error::from(format!("unknown filter type {} on row {}", filter, row))),
+            };
+        }
+    }
+    Ok(out)
+}
+
+/// Filters one scanline with each filter type and keeps the result with the smallest sum of
+/// absolute values, the heuristic the spec suggests for picking filters
+fn filter_row(row: &[u8], previous: Option<&[u8]>, bpp: usize) -> Vec<u8> {
+    (0..5u8)
+        .map(|filter| {
+            let mut line = vec![filter];
+            for i in 0..row.len() {
+                let a = if i >= bpp { row[i - bpp] } else { 0 };
+                let b = previous.map_or(0, |previous| previous[i]);
+                let c = if i >= bpp { previous.map_or(0, |previous| previous[i - bpp]) } else { 0 };
+                line.push(match filter {
+                    0 => row[i],
+                    1 => row[i].wrapping_sub(a),
+                    2 => row[i].wrapping_sub(b),
+                    3 => row[i].wrapping_sub(((a as u16 + b as u16) / 2) as u8),
+                    _ => row[i].wrapping_sub(paeth(a, b, c)),
+                });
+            }
+            line
+        })
+        .min_by_key(|line| line[1..].iter().map(|&v| (v as i8).unsigned_abs() as usize).sum::<usize>())
+        .unwrap()
+}
+
+/// Splits an unfiltered scanline into its samples
+fn unpack_row(row: &[u8], samples: usize, bit_depth: u8) -> impl Iterator<Item = u16> + '_ {
+    (0..samples).map(move |i| match bit_depth {
+        16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]),
+        8 => row[i] as u16,
+        depth => {
+            let depth = depth as usize;
+            let bit = i * depth;
+            let shift = 8 - depth - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8) as u16
+        }
+    })
+}
+
+/// Packs samples into a scanline, the reverse of `unpack_row`
+fn pack_row(samples: &[u16], bit_depth: u8) -> Vec<u8> {
+    match bit_depth {
+        16 => samples.iter().flat_map(|sample| sample.to_be_bytes()).collect(),
+        8 => samples.iter().map(|&sample| sample as u8).collect(),
+        depth => {
+            let depth = depth as usize;
+            let mut row = vec![0u8; (samples.len() * depth).div_ceil(8)];
+            for (i, &sample) in samples.iter().enumerate() {
+                let bit = i * depth;
+                row[bit / 8] |= (sample as u8) << (8 - depth - bit % 8);
+            }
+            row
+        }
+    }
+}
+
+/// Filters and compresses the samples of an image into the data of its IDAT chunks.
+/// Only non-interlaced images can be encoded.
+pub fn encode(image: &Image) -> Result<Vec<u8>> {
+    let header = &image.header;
+    if header.interlaced {
+        return Err(Error::from("encoding interlaced images is not supported"));
+    }
+    let stride = image.width() * image.channels();
+    if image.samples.len() != stride * image.height() {
+        return Err(Error::from(format!(
+            "expected {} samples for a {}x{} image, got {}",
+            stride * image.height(),
+            header.width,
+            header.height,
+            image.samples.len()
+        )));
+    }
+    let max = (1u32 << header.bit_depth) - 1;
+    if let Some(sample) = image.samples.iter().find(|&&sample| sample as u32 > max) {
+        return Err(Error::from(format!("sample {} does not fit in {} bits", sample, header.bit_depth)));
+    }
+
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    let mut previous: Option<Vec<u8>> = None;
+    for samples in image.samples.chunks(stride) {
+        let row = pack_row(samples, header.bit_depth);
+        // the spec recommends leaving palette images and packed samples unfiltered
+        let line = if header.color_type == 3 || header.bit_depth < 8 {
+            [&[0][..], &row].concat()
+        } else {
+            filter_row(&row, previous.as_deref(), bpp)
+        };
+        encoder.write_all(&line)?;
+        previous = Some(row);
+    }
+    Ok(encoder.finish()?)
+}
+
+/// Decodes the pixel samples of a PNG, undoing compression, filtering and interlacing
+pub fn decode(png: &Png) -> Result<Image> {
+    let header = Header::from_png(png)?;
+    let expected = header.raw_size()?;
+    let raw = inflate_idat(png, expected)?;
+    if raw.len() < expected {
+        return Err(Error::from("image data is shorter than the header requires"));
+    }
+    // the data is all there, so the samples take at most sixteen times its size
+    let (width, channels) = (header.width as usize, header.channels());
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let mut samples = vec![0u16; width * header.height as usize * channels];
+    let mut offset = 0;
+    for pass in header.passes() {
+        let Some((pass_width, pass_height)) = header.pass_size(pass) else { continue };
+        let (x0, y0, dx, dy) = pass;
+        let row_bytes = header.row_bytes(pass_width);
+        let size = pass_height * (row_bytes + 1);
+        let rows = unfilter(&raw[offset..offset + size], pass_height, row_bytes, bpp)?;
+        offset += size;
+        for (r, row) in rows.chunks(row_bytes).enumerate() {
+            let y = y0 + r * dy;
+            for (i, sample) in unpack_row(row, pass_width * channels, header.bit_depth).enumerate() {
+                let x = x0 + (i / channels) * dx;
+                samples[(y * width + x) * channels + i % channels] = sample;
+            }
+        }
+    }
+    Ok(Image { header, samples })
+}
+
+/// Scales a sample of `bit_depth` bits to the full 16 bit range
+fn widen(sample: u16, bit_depth: u8) -> u16 {
+    let max = (1u32 << bit_depth) - 1;
+    (sample as u32 * 65535 / max) as u16
+}
+
+/// Converts decoded samples to 16 bit RGBA, resolving palette indices through PLTE and
+/// transparency through tRNS, so images stored with different color types can be compared
+pub fn rgba(png: &Png, image: &Image) -> Result<Vec<[u16; 4]>> {
+    let header = &image.header;
+    let depth = header.bit_depth;
+    let trns = png.chunk_by_type(&ChunkType::TRNS).map(|chunk| chunk.data().to_vec()).unwrap_or_default();
+    let key: Vec<u16> = trns.chunks_exact(2).map(|v| u16::from_be_bytes([v[0], v[1]])).collect();
+    let palette = png.chunk_by_type(&ChunkType::PLTE).map(|chunk| chunk.data().to_vec());
+
+    image
+        .samples
+        .chunks_exact(image.channels())
+        .map(|pixel| {
+            let opaque = |matches_key: bool| if matches_key { 0 } else { 65535 };
+            let color = match header.color_type {
+                0 => {
+                    let gray = widen(pixel[0], depth);
+                    [gray, gray, gray, opaque(key.first() == Some(&pixel[0]))]
+                }
+                2 => {
+                    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|v| widen(v, depth));
+                    [r, g, b, opaque(key.len() == 3 && key[..] == pixel[..3])]
+                }
+                3 => {
+                    let index = pixel[0] as usize;
+                    let entry = palette
+                        .as_ref()
+                        .and_then(|palette| palette.get(index * 3..index * 3 + 3))
+                        .ok_or_else(|| Error::from(format!("palette index {} has no PLTE entry", index)))?;
+                    let alpha = trns.get(index).copied().unwrap_or(255);
+                    [entry[0], entry[1], entry[2], alpha].map(|v| v as u16 * 257)
+                }
+                4 => {
+                    let gray = widen(pixel[0], depth);
+                    [gray, gray, gray, widen(pixel[1], depth)]
+                }
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]].map(|v| widen(v, depth)),
+            };
+            Ok(color)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    #[test]
+    fn test_header_from_png() {
+        let png = Png::try_from(DICE).unwrap();
+        let header = Header::from_png(&png).unwrap();
+
+        assert_eq!((header.width, header.height), (420, 315));
+        assert_eq!((header.bit_depth, header.color_type), (8, 6));
+        assert_eq!(header.channels(), 4);
+        assert!(header.has_alpha());
+        assert!(!header.interlaced);
+    }
+
+    #[test]
+    fn test_row_bytes_packed() {
+        let header = Header { width: 10, height: 1, bit_depth: 1, color_type: 0, interlaced: false };
+        assert_eq!(header.row_bytes(10), 2);
+    }
+
+    #[test]
+    fn test_decode_dice() {
+        let png = Png::try_from(DICE).unwrap();
+        let image = decode(&png).unwrap();
+
+        assert_eq!(image.samples.len(), 420 * 315 * 4);
+        assert_eq!(image.row_channel(0, 3).count(), 420);
+    }
+
+    #[test]
+    fn test_decode_rejects_hostile_headers() {
+        let header = Header { width: 0x7fffffff, height: 0x7fffffff, bit_depth: 16, color_type: 6, interlaced: false };
+        let png = Png::from_chunks(vec![header.to_chunk().unwrap(), idat(&[0; 9])]);
+        assert!(header.raw_size().is_err());
+        assert!(decode(&png).is_err());
+
+        // a single gray pixel whose data inflates far past the two bytes it needs
+        let header = Header { width: 1, height: 1, bit_depth: 8, color_type: 0, interlaced: false };
+        let png = Png::from_chunks(vec![header.to_chunk().unwrap(), idat(&vec![0; 1 << 20])]);
+        assert_eq!(header.raw_size().unwrap(), 2);
+        assert!(decode(&png).is_err());
+    }
+
+    fn idat(raw: &[u8]) -> Chunk {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(raw).unwrap();
+        Chunk::new(ChunkType::IDAT, encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn test_raw_size_interlaced() {
+        let header = Header { width: 3, height: 3, bit_depth: 8, color_type: 0, interlaced: true };
+        // passes 1, 4, 5, 6 and 7 hold 1x1, 1x1, 2x1, 1x2 and 3x1 pixels; 2 and 3 are empty
+        assert_eq!(header.raw_size().unwrap(), 2 + 2 + 3 + 2 * 2 + 4);
+    }
+
+    #[test]
+    fn test_unfilter_sub_and_up() {
+        #[rustfmt::skip]
+        let data = [
+            1, 10, 5, 5,   // sub: 10, 15, 20
+            2, 1, 1, 1,    // up: 11, 16, 21
+        ];
+        let out = unfilter(&data, 2, 3, 1).unwrap();
+        assert_eq!(out, vec![10, 15, 20, 11, 16, 21]);
+    }
+
+    #[test]
+    fn test_rgba_resolves_palette() {
+        let header = Header { width: 2, height: 1, bit_depth: 1, color_type: 3, interlaced: false };
+        let image = Image { header, samples: vec![1, 0] };
+        let mut png = Png::from_chunks(vec![]);
+        png.append_chunk(Chunk::new("PLTE".parse().unwrap(), vec![0, 0, 0, 255, 128, 0]));
+        png.append_chunk(Chunk::new("tRNS".parse().unwrap(), vec![0]));
+
+        let pixels = rgba(&png, &image).unwrap();
+        assert_eq!(pixels, vec![[65535, 128 * 257, 0, 65535], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn test_rgba_widens_gray() {
+        let header = Header { width: 2, height: 1, bit_depth: 2, color_type: 0, interlaced: false };
+        let image = Image { header, samples: vec![3, 1] };
+        let pixels = rgba(&Png::from_chunks(vec![]), &image).unwrap();
+        assert_eq!(pixels, vec![[65535, 65535, 65535, 65535], [21845, 21845, 21845, 65535]]);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let rows: [[u8; 6]; 3] = [[10, 20, 30, 40, 50, 60], [12, 22, 32, 42, 52, 62], [255, 0, 255, 0, 255, 0]];
+        let mut data = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            data.extend(filter_row(row, i.checked_sub(1).map(|p| &rows[p][..]), 2));
+        }
+        assert_eq!(unfilter(&data, 3, 6, 2).unwrap(), rows.concat());
+    }
+
+    #[test]
+    fn test_pack_row_reverses_unpack() {
+        let samples = [3, 2, 1, 0, 1];
+        let row = pack_row(&samples, 2);
+        assert_eq!(row, vec![0b11_10_01_00, 0b01_00_00_00]);
+        assert_eq!(unpack_row(&row, 5, 2).collect::<Vec<_>>(), samples);
+    }
+
+    #[test]
+    fn test_unpack_two_bit_row() {
+        let samples: Vec<u16> = unpack_row(&[0b11_10_01_00], 4, 2).collect();
+        assert_eq!(samples, vec![3, 2, 1, 0]);
+    }
+}
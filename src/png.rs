@@ -1,52 +1,285 @@
-#![allow(unused_variables)]
 use std::{vec, fmt};
+use std::collections::HashMap;
+use std::path::Path;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::atomic;
 use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
 use crate::{Error, Result};
 
-#[derive(Debug)]
-
+/// A PNG file: its chunks in order, plus any bytes found after the IEND chunk.
+/// The chunk list only changes through the methods below, which keep an index from each
+/// chunk type to its positions up to date.
+#[derive(Debug, Clone)]
 pub struct Png {
-    pub data: Vec<Chunk>,
+    data: Vec<Chunk>,
+    trailer: Vec<u8>,
+    index: HashMap<ChunkType, Vec<usize>>,
 }
 
-#[allow(dead_code)]
 impl Png {
+    /// The eight bytes every PNG file starts with
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+    /// A PNG made of `chunks` as given, with nothing after them
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Png { data: chunks }
+        Png::with_trailer(chunks, vec![])
+    }
+
+    fn with_trailer(data: Vec<Chunk>, trailer: Vec<u8>) -> Png {
+        let mut png = Png { data, trailer, index: HashMap::new() };
+        png.reindex();
+        png
+    }
+
+    /// Rebuilds the index from type to positions after the chunk list changed
+    fn reindex(&mut self) {
+        self.index.clear();
+        for (position, chunk) in self.data.iter().enumerate() {
+            self.index.entry(chunk.chunk_type().clone()).or_default().push(position);
+        }
+    }
+
+    /// Moves every indexed position at or after `from` one place up, after a chunk was
+    /// inserted at `from`, or down, after one was removed from there
+    fn shift_positions(&mut self, from: usize, up: bool) {
+        for positions in self.index.values_mut() {
+            let start = positions.partition_point(|&position| position < from);
+            for position in &mut positions[start..] {
+                if up {
+                    *position += 1;
+                } else {
+                    *position -= 1;
+                }
+            }
+        }
+    }
+
+    /// Records the chunk just inserted at `index`, shifting only the positions after it
+    fn index_inserted(&mut self, index: usize) {
+        self.shift_positions(index, true);
+        let positions = self.index.entry(self.data[index].chunk_type().clone()).or_default();
+        let at = positions.partition_point(|&position| position < index);
+        positions.insert(at, index);
+    }
+
+    /// Forgets a chunk of `chunk_type` just removed from `index`
+    fn index_removed(&mut self, index: usize, chunk_type: &ChunkType) {
+        if let Some(positions) = self.index.get_mut(chunk_type) {
+            if let Ok(at) = positions.binary_search(&index) {
+                positions.remove(at);
+            }
+            if positions.is_empty() {
+                self.index.remove(chunk_type);
+            }
+        }
+        self.shift_positions(index, false);
+    }
+
+    /// Reads and parses a PNG file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Png> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|err| Error::from(format!("Error reading `{:?}`: {}", path, err)))?;
+        Png::try_from(&bytes[..])
+    }
+
+    /// Writes the PNG to `path` atomically, so readers never see a partly written file
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        atomic::write_atomic(path, &self.as_bytes(), None)
+            .map_err(|err| Error::from(format!("failed to write {:?}: {}", path, err)))
     }
 
+    /// Appends a chunk, keeping it in front of IEND so the image stays valid
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.data.push(chunk);
+        match self.data.last() {
+            Some(last) if *last.chunk_type() == ChunkType::IEND => {
+                let end = self.data.len() - 1;
+                let chunk_type = chunk.chunk_type().clone();
+                self.data.insert(end, chunk);
+                if chunk_type == ChunkType::IEND {
+                    self.index_inserted(end);
+                } else {
+                    // only the final IEND moves, and every other chunk of this type comes before it
+                    if let Some(position) = self.index.get_mut(&ChunkType::IEND).and_then(|positions| positions.last_mut()) {
+                        *position += 1;
+                    }
+                    self.index.entry(chunk_type).or_default().push(end);
+                }
+            }
+            _ => {
+                self.index.entry(chunk.chunk_type().clone()).or_default().push(self.data.len());
+                self.data.push(chunk);
+            }
+        }
+    }
+
+    /// Inserts a chunk at `index`, so that it becomes the chunk at that position
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.data.len() {
+            return Err(Error::from(format!(
+                "index {} is past the end of the {} chunks",
+                index,
+                self.data.len()
+            )));
+        }
+        self.data.insert(index, chunk);
+        self.index_inserted(index);
+        Ok(())
+    }
+
+    /// Moves the chunk at `from` so that it ends up at index `to`
+    pub fn move_chunk(&mut self, from: usize, to: usize) -> Result<()> {
+        let len = self.data.len();
+        if from >= len || to >= len {
+            return Err(Error::from(format!("cannot move chunk {} to {}, there are {} chunks", from, to, len)));
+        }
+        let chunk = self.data.remove(from);
+        self.index_removed(from, chunk.chunk_type());
+        self.data.insert(to, chunk);
+        self.index_inserted(to);
+        Ok(())
+    }
+
+    /// Bytes found after the IEND chunk, empty for a well formed file
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Replaces the bytes after IEND
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    /// Removes the first chunk of the given type
+    pub fn remove_chunk(&mut self, chunk_type: &ChunkType) -> Result<Chunk> {
+        match self.positions(chunk_type).first() {
+            Some(&index) => self.remove_at(index),
+            None => Err(Error::from(format!("no `{}` chunk", chunk_type))),
+        }
+    }
+
+    /// Removes every chunk of the given type and returns them in file order
+    pub fn remove_all(&mut self, chunk_type: &ChunkType) -> Vec<Chunk> {
+        if !self.index.contains_key(chunk_type) {
+            return vec![];
+        }
+        let (removed, kept) = self.data.drain(..).partition(|chunk| chunk.chunk_type() == chunk_type);
+        self.data = kept;
+        self.reindex();
+        removed
     }
 
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        if let Some(ind) = self
+    /// Removes the chunk at `index`
+    pub fn remove_at(&mut self, index: usize) -> Result<Chunk> {
+        if index >= self.data.len() {
+            return Err(Error::from(format!("no chunk {}, there are {} chunks", index, self.data.len())));
+        }
+        let chunk = self.data.remove(index);
+        self.index_removed(index, chunk.chunk_type());
+        Ok(chunk)
+    }
+
+    /// Puts `chunk` in place of the chunk at `index` and returns the old one
+    pub fn replace_at(&mut self, index: usize, chunk: Chunk) -> Result<Chunk> {
+        let len = self.data.len();
+        let slot = self
             .data
-            .iter()
-            .position(|x| x.chunk_type().to_string() == chunk_type)
-        {
-            return Ok(self.data.remove(ind));
+            .get_mut(index)
+            .ok_or_else(|| Error::from(format!("no chunk {}, there are {} chunks", index, len)))?;
+        let same_type = slot.chunk_type() == chunk.chunk_type();
+        let old = std::mem::replace(slot, chunk);
+        if !same_type {
+            self.reindex();
+        }
+        Ok(old)
+    }
+
+    /// Edits the chunk at `index` in place, e.g. through `Chunk::set_data`, and returns what
+    /// `edit` returns. The type index is rebuilt if `edit` leaves a chunk of another type.
+    pub fn update_chunk<R>(&mut self, index: usize, edit: impl FnOnce(&mut Chunk) -> R) -> Result<R> {
+        let len = self.data.len();
+        let chunk = self
+            .data
+            .get_mut(index)
+            .ok_or_else(|| Error::from(format!("no chunk {}, there are {} chunks", index, len)))?;
+        let before = chunk.chunk_type().clone();
+        let result = edit(chunk);
+        if *chunk.chunk_type() != before {
+            self.reindex();
+        }
+        Ok(result)
+    }
+
+    /// Puts `chunk` in place of the first chunk of its type and returns the old one
+    pub fn replace_chunk(&mut self, chunk: Chunk) -> Result<Chunk> {
+        match self.positions(chunk.chunk_type()).first() {
+            Some(&index) => self.replace_at(index, chunk),
+            None => Err(Error::from(format!("no `{}` chunk to replace", chunk.chunk_type()))),
         }
-        Err(Error::from("element not found"))
     }
 
+    /// Keeps only the chunks for which `keep` returns true
+    pub fn retain(&mut self, keep: impl FnMut(&Chunk) -> bool) {
+        self.data.retain(keep);
+        self.reindex();
+    }
+
+    /// Sorts the chunks by a key computed from each chunk and its current index, keeping
+    /// chunks with equal keys in their current order
+    pub fn sort_chunks_by_key<K: Ord>(&mut self, mut key: impl FnMut(usize, &Chunk) -> K) {
+        let mut keyed: Vec<(K, Chunk)> = self
+            .data
+            .drain(..)
+            .enumerate()
+            .map(|(index, chunk)| (key(index, &chunk), chunk))
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        self.data = keyed.into_iter().map(|(_, chunk)| chunk).collect();
+        self.reindex();
+    }
+
+    /// The chunks in file order
     pub fn chunks(&self) -> &[Chunk] {
         &self.data[..]
     }
 
-    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        let mut chunk_iter: std::slice::Iter<'_, Chunk> = self.data.iter();
-        println!("{:?}",chunk_type);
-        if let Some(ind) =
-            chunk_iter.position(|x| x.chunk_type().to_string() == chunk_type.to_string())
-        {
-            return Some(&self.data[ind]);
-        }
-        None
+    /// File offset of the start (length field) of every chunk
+    pub fn offsets(&self) -> Vec<usize> {
+        let mut offset = Png::STANDARD_HEADER.len();
+        self.data
+            .iter()
+            .map(|chunk| {
+                let start = offset;
+                offset += chunk.length() as usize + 12;
+                start
+            })
+            .collect()
     }
 
+    /// The first chunk of the given type
+    pub fn chunk_by_type(&self, chunk_type: &ChunkType) -> Option<&Chunk> {
+        self.positions(chunk_type).first().map(|&index| &self.data[index])
+    }
+
+    /// Every chunk of the given type, in file order
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &ChunkType) -> impl Iterator<Item = &'a Chunk> + 'a {
+        self.positions(chunk_type).iter().map(|&index| &self.data[index])
+    }
+
+    /// Indices of the chunks of the given type, in file order
+    pub fn positions(&self, chunk_type: &ChunkType) -> &[usize] {
+        self.index.get(chunk_type).map_or(&[], Vec::as_slice)
+    }
+
+    /// The first chunk for which `predicate` returns true
+    pub fn find(&self, mut predicate: impl FnMut(&Chunk) -> bool) -> Option<&Chunk> {
+        self.data.iter().find(|chunk| predicate(chunk))
+    }
+
+    /// The whole file: signature, chunks and trailer
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut whole_vector: Vec<u8> = vec![137, 80, 78, 71, 13, 10, 26, 10];
 
@@ -55,38 +288,99 @@ impl Png {
                 whole_vector.push(byte);
             }
         }
+        whole_vector.extend(self.trailer.iter());
         whole_vector
     }
-}
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
-    fn try_from(value: &[u8]) -> Result<Self> {
+    /// Parses a file like `try_from` but accepts chunks whose stored CRC is wrong, keeping
+    /// it as is, so that damaged files can still be inspected and repaired
+    pub fn parse_unchecked(bytes: &[u8]) -> Result<Png> {
+        Png::parse(bytes, false)
+    }
+
+    fn parse(value: &[u8], check_crc: bool) -> Result<Png> {
         if value.len() < 8 {
             return Err(Error::from("not a valid file"));
         }
 
-        if &value[..8] != Png::STANDARD_HEADER {
+        if value[..8] != Png::STANDARD_HEADER {
             return Err(Error::from("not a valid png file"));
         }
         let mut chunk_vector: Vec<Chunk> = vec![];
         let mut i = 8;
         while i < value.len(){
-          
-            let length: &[u8; 4] = &value[i..i+4].try_into().unwrap();
-            let length:usize = u32::from_be_bytes(length.clone()) as usize; 
-            
-            
-            let chunk = value[i..i+length+12].to_vec();
-            let real_thing = Chunk::try_from(chunk.as_ref()).unwrap();
-            // let crc = u32::from_be_bytes(value[i+length+8..i+length+12].try_into().unwrap());
-           
+            if value.len() - i < 12 {
+                return Err(Error::from(format!("truncated chunk at offset {}", i)));
+            }
+            let length: [u8; 4] = value[i..i+4].try_into().unwrap();
+            let length:usize = u32::from_be_bytes(length) as usize; 
+            if value.len() - i - 12 < length {
+                return Err(Error::from(format!("chunk at offset {} runs past the end of the file", i)));
+            }
+
+            let real_thing = Chunk::parse_unchecked(&value[i..i+length+12])?;
+            if check_crc && !real_thing.crc_matches() {
+                return Err(Error::from(format!(
+                    "checksum mismatch in `{}` chunk at offset {}",
+                    real_thing.chunk_type(),
+                    i
+                )));
+            }
+            let is_end = *real_thing.chunk_type() == ChunkType::IEND;
+
             chunk_vector.push(real_thing);
             i += 12 + length; 
+            if is_end {
+                break;
+            }
         }
-        Ok(Png {
-            data: chunk_vector,
-        })
+        Ok(Png::with_trailer(chunk_vector, value[i..].to_vec()))
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+    fn try_from(value: &[u8]) -> Result<Self> {
+        Png::parse(value, true)
+    }
+}
+
+#[derive(Serialize)]
+struct SerializedPng<'a> {
+    chunks: &'a [Chunk],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trailer: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeserializedPng {
+    chunks: Vec<Chunk>,
+    trailer: Option<String>,
+}
+
+/// Serialized as the list of chunks and, when there is one, the base64 trailer. The
+/// chunks come back exactly as listed, so a dump rebuilds the original file byte for byte.
+impl Serialize for Png {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        SerializedPng {
+            chunks: &self.data,
+            trailer: (!self.trailer.is_empty()).then(|| BASE64.encode(&self.trailer)),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Png {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let png = DeserializedPng::deserialize(deserializer)?;
+        let trailer = match png.trailer {
+            Some(trailer) => BASE64.decode(trailer).map_err(|err| {
+                serde::de::Error::custom(format!("trailer is not valid base64: {}", err))
+            })?,
+            None => vec![],
+        };
+        Ok(Png::with_trailer(png.chunks, trailer))
     }
 }
 
@@ -105,13 +399,11 @@ mod tests {
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -168,6 +460,19 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_parse_unchecked_accepts_bad_crc() {
+        let mut bytes = Png::from_chunks(testing_chunks()).as_bytes();
+        let crc_of_first = 8 + 12 + "I am the first chunk".len() - 1;
+        bytes[crc_of_first] ^= 0xff;
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+        let png = Png::parse_unchecked(&bytes).unwrap();
+        assert!(!png.chunks()[0].crc_matches());
+        assert!(png.chunks()[1].crc_matches());
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
     #[test]
     fn test_invalid_chunk() {
         let mut chunk_bytes: Vec<u8> = testing_chunks()
@@ -200,7 +505,7 @@ mod tests {
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
-        let chunk = png.chunk_by_type("FrSt").unwrap();
+        let chunk = png.chunk_by_type(&ChunkType::from_str("FrSt").unwrap()).unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
@@ -209,21 +514,168 @@ mod tests {
     fn test_append_chunk() {
         let mut png = testing_png();
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-        let chunk = png.chunk_by_type("TeSt").unwrap();
+        let chunk = png.chunk_by_type(&ChunkType::from_str("TeSt").unwrap()).unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        png.insert_chunk(4, chunk_from_strings("EnDs", "Message").unwrap()).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+
+        assert_eq!(types, vec!["FrSt", "TeSt", "miDl", "LASt", "EnDs"]);
+        assert!(png.insert_chunk(6, chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_move_chunk() {
+        let mut png = testing_png();
+        png.move_chunk(0, 2).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["miDl", "LASt", "FrSt"]);
+
+        png.move_chunk(2, 0).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+
+        assert!(png.move_chunk(0, 3).is_err());
+        assert!(png.move_chunk(3, 0).is_err());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-        png.remove_chunk("TeSt").unwrap();
+        png.remove_chunk(&ChunkType::from_str("TeSt").unwrap()).unwrap();
 
-        let chunk = png.chunk_by_type("TeSt");
+        let chunk = png.chunk_by_type(&ChunkType::from_str("TeSt").unwrap());
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_index_follows_mutations() {
+        let mut png = testing_png();
+        let test = ChunkType::from_str("TeSt").unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "one").unwrap());
+        png.insert_chunk(0, chunk_from_strings("TeSt", "two").unwrap()).unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "three").unwrap());
+        assert_eq!(png.positions(&test), [0, 4, 5]);
+
+        png.move_chunk(0, 2).unwrap();
+        png.remove_at(0).unwrap();
+        assert_eq!(png.positions(&test), [1, 3, 4]);
+        let texts: Vec<String> = png.chunks_by_type(&test).map(|c| c.data_as_string().unwrap()).collect();
+        assert_eq!(texts, vec!["two", "one", "three"]);
+
+        png.replace_at(1, chunk_from_strings("FrSt", "again").unwrap()).unwrap();
+        assert_eq!(png.positions(&test), [3, 4]);
+        assert_eq!(png.positions(&ChunkType::from_str("FrSt").unwrap()), [1]);
+
+        png.retain(|c| c.data_as_string().unwrap() != "one");
+        assert_eq!(png.positions(&test), [3]);
+        assert!(png.positions(&ChunkType::from_str("NoNe").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_index_matches_rebuilt_index() {
+        let mut png = Png::try_from(&testing_png().as_bytes()[..]).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::IEND, vec![]));
+        for i in 0..40 {
+            let name = ["TeSt", "FrSt", "IEND", "miDl"][i % 4];
+            png.append_chunk(chunk_from_strings(name, "x").unwrap());
+            if i % 3 == 0 {
+                png.insert_chunk(i % 7, chunk_from_strings("InSt", "y").unwrap()).unwrap();
+            }
+            if i % 5 == 0 {
+                png.move_chunk(i % 11, (i * 7) % png.chunks().len()).unwrap();
+                png.remove_at(i % 13).unwrap();
+            }
+            let rebuilt = Png::from_chunks(png.chunks().to_vec());
+            assert_eq!(png.index, rebuilt.index, "after step {}", i);
+        }
+    }
+
+    #[test]
+    fn test_remove_all_and_replace_chunk() {
+        let mut png = testing_png();
+        let test = ChunkType::from_str("TeSt").unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "one").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "two").unwrap());
+
+        let old = png.replace_chunk(chunk_from_strings("TeSt", "new").unwrap()).unwrap();
+        assert_eq!(old.data_as_string().unwrap(), "one");
+        assert_eq!(png.chunk_by_type(&test).unwrap().data_as_string().unwrap(), "new");
+
+        let removed = png.remove_all(&test);
+        assert_eq!(removed.len(), 2);
+        assert!(png.chunk_by_type(&test).is_none());
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.replace_chunk(chunk_from_strings("TeSt", "none").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_update_chunk() {
+        let mut png = testing_png();
+        png.update_chunk(1, |chunk| chunk.data_mut().extend_from_slice(b"!")).unwrap();
+        assert_eq!(png.chunks()[1].data_as_string().unwrap(), "I am another chunk!");
+        assert!(png.chunks()[1].crc_matches());
+
+        png.update_chunk(1, |chunk| *chunk = chunk.clone().with_type(ChunkType::from_str("TeSt").unwrap())).unwrap();
+        assert_eq!(png.positions(&ChunkType::from_str("TeSt").unwrap()), [1]);
+        assert!(png.chunk_by_type(&ChunkType::from_str("miDl").unwrap()).is_none());
+        assert!(png.update_chunk(3, |_| ()).is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let png = testing_png();
+        let chunk = png.find(|c| c.data_as_string().unwrap().contains("another")).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "miDl");
+        assert!(png.find(|c| c.length() > 100).is_none());
+    }
+
+    #[test]
+    fn test_truncated_chunk_is_err() {
+        let png = Png::try_from(&PNG_FILE[..PNG_FILE.len() - 3]);
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_trailer_after_iend() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(b"hidden after the end");
+        let png = Png::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(png.trailer(), b"hidden after the end");
+        assert_eq!(&png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_append_chunk_keeps_iend_last() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+
+        assert_eq!(types[types.len() - 2], "TeSt");
+        assert_eq!(types[types.len() - 1], "IEND");
+    }
+
+    #[test]
+    fn test_offsets() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offsets = png.offsets();
+
+        assert_eq!(offsets[0], 8);
+        assert_eq!(offsets[1], 8 + 12 + 13);
+        for (chunk, offset) in png.chunks().iter().zip(offsets) {
+            assert_eq!(&PNG_FILE[offset + 4..offset + 8], &chunk.chunk_type().bytes());
+        }
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -256,6 +708,18 @@ mod tests {
         let _png_string = format!("{}", png);
     }
 
+    #[test]
+    fn test_png_serde_round_trip() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_trailer(b"\x00\xffafter".to_vec());
+        let bytes = png.as_bytes();
+
+        let json = serde_json::to_string(&png).unwrap();
+        assert_eq!(serde_json::from_str::<Png>(&json).unwrap().as_bytes(), bytes);
+        let yaml = serde_norway::to_string(&png).unwrap();
+        assert_eq!(serde_norway::from_str::<Png>(&yaml).unwrap().as_bytes(), bytes);
+    }
+
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
     const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
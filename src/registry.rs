@@ -0,0 +1,214 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::chunk_type::ChunkType;
+
+/// How many chunks of a type a file may contain
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Multiplicity {
+    /// Exactly one
+    One,
+    /// One or more
+    OneOrMore,
+    /// None or one
+    AtMostOne,
+    /// Any number
+    Any,
+}
+
+impl Multiplicity {
+    /// The most chunks of this type a file may contain, if there is a limit
+    pub fn limit(self) -> Option<usize> {
+        match self {
+            Multiplicity::One | Multiplicity::AtMostOne => Some(1),
+            Multiplicity::OneOrMore | Multiplicity::Any => None,
+        }
+    }
+
+    /// True when a file without a chunk of this type is invalid
+    pub fn is_required(self) -> bool {
+        matches!(self, Multiplicity::One | Multiplicity::OneOrMore)
+    }
+}
+
+impl fmt::Display for Multiplicity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Multiplicity::One => "exactly one",
+            Multiplicity::OneOrMore => "one or more",
+            Multiplicity::AtMostOne => "at most one",
+            Multiplicity::Any => "any number",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Where in the file a chunk type must appear
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// In front of IHDR, which only happens in files that are not standard PNGs
+    BeforeIhdr,
+    /// The first chunk
+    First,
+    /// Before PLTE and IDAT
+    BeforePlte,
+    /// The palette itself, before IDAT
+    Palette,
+    /// After PLTE, if there is one, and before IDAT
+    AfterPlte,
+    /// Anywhere before IDAT
+    BeforeIdat,
+    /// The image data, in consecutive chunks
+    ImageData,
+    /// Anywhere after IDAT
+    AfterIdat,
+    /// The last chunk
+    Last,
+    /// Anywhere between the first and last chunk
+    Anywhere,
+}
+
+impl Placement {
+    /// Position in the sequence the spec requires: every ranked chunk must come after all
+    /// chunks of a lower rank. Chunks that may go anywhere have no rank.
+    pub fn rank(self) -> Option<u8> {
+        match self {
+            Placement::BeforeIhdr => Some(0),
+            Placement::First => Some(2),
+            Placement::BeforePlte => Some(4),
+            Placement::Palette => Some(6),
+            Placement::AfterPlte | Placement::BeforeIdat => Some(8),
+            Placement::ImageData => Some(10),
+            Placement::AfterIdat => Some(11),
+            Placement::Last => Some(12),
+            Placement::Anywhere => None,
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Placement::BeforeIhdr => "before IHDR",
+            Placement::First => "first",
+            Placement::BeforePlte => "before PLTE and IDAT",
+            Placement::Palette => "before IDAT",
+            Placement::AfterPlte => "after PLTE, before IDAT",
+            Placement::BeforeIdat => "before IDAT",
+            Placement::ImageData => "consecutive, after PLTE",
+            Placement::AfterIdat => "after IDAT",
+            Placement::Last => "last",
+            Placement::Anywhere => "anywhere",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// What is known about a chunk type
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The specification or vendor that defines the type
+    pub source: &'static str,
+    pub multiplicity: Multiplicity,
+    pub placement: Placement,
+}
+
+const PNG: &str = "PNG";
+const APNG: &str = "APNG";
+const EXTENSIONS: &str = "PNG extensions";
+
+const fn info(
+    name: &'static str,
+    description: &'static str,
+    source: &'static str,
+    multiplicity: Multiplicity,
+    placement: Placement,
+) -> ChunkInfo {
+    ChunkInfo { name, description, source, multiplicity, placement }
+}
+
+use Multiplicity::*;
+use Placement::*;
+
+/// Every chunk type the tool knows: the registered types of the PNG specification, APNG
+/// and the PNG extensions, and a few private types that common software writes
+pub static REGISTRY: [ChunkInfo; 43] = [
+    info("IHDR", "image header", PNG, One, First),
+    info("PLTE", "palette", PNG, AtMostOne, Palette),
+    info("IDAT", "image data", PNG, OneOrMore, ImageData),
+    info("IEND", "end of the image", PNG, One, Last),
+    info("tRNS", "transparency", PNG, AtMostOne, AfterPlte),
+    info("cHRM", "primary chromaticities and white point", PNG, AtMostOne, BeforePlte),
+    info("gAMA", "image gamma", PNG, AtMostOne, BeforePlte),
+    info("iCCP", "embedded ICC profile", PNG, AtMostOne, BeforePlte),
+    info("sBIT", "significant bits", PNG, AtMostOne, BeforePlte),
+    info("sRGB", "standard RGB color space", PNG, AtMostOne, BeforePlte),
+    info("cICP", "coding-independent code points", PNG, AtMostOne, BeforePlte),
+    info("mDCV", "mastering display color volume", PNG, AtMostOne, BeforePlte),
+    info("cLLI", "content light level information", PNG, AtMostOne, BeforePlte),
+    info("tEXt", "textual data", PNG, Any, Anywhere),
+    info("zTXt", "compressed textual data", PNG, Any, Anywhere),
+    info("iTXt", "international textual data", PNG, Any, Anywhere),
+    info("bKGD", "background color", PNG, AtMostOne, AfterPlte),
+    info("hIST", "image histogram", PNG, AtMostOne, AfterPlte),
+    info("pHYs", "physical pixel dimensions", PNG, AtMostOne, BeforeIdat),
+    info("sPLT", "suggested palette", PNG, Any, BeforeIdat),
+    info("eXIf", "Exif metadata", PNG, AtMostOne, BeforeIdat),
+    info("tIME", "last modification time", PNG, AtMostOne, Anywhere),
+    info("acTL", "animation control", APNG, AtMostOne, BeforeIdat),
+    info("fcTL", "frame control", APNG, Any, Anywhere),
+    info("fdAT", "frame data", APNG, Any, AfterIdat),
+    info("oFFs", "image offset", EXTENSIONS, AtMostOne, BeforeIdat),
+    info("pCAL", "pixel value calibration", EXTENSIONS, AtMostOne, BeforeIdat),
+    info("sCAL", "physical scale of image subject", EXTENSIONS, AtMostOne, BeforeIdat),
+    info("sTER", "stereo image indicator", EXTENSIONS, AtMostOne, BeforeIdat),
+    info("gIFg", "GIF graphic control extension", EXTENSIONS, Any, Anywhere),
+    info("gIFx", "GIF application extension", EXTENSIONS, Any, Anywhere),
+    info("gIFt", "GIF plain text extension (deprecated)", EXTENSIONS, Any, Anywhere),
+    info("dSIG", "digital signature", EXTENSIONS, Any, Anywhere),
+    info("fRAc", "fractal image parameters", EXTENSIONS, Any, Anywhere),
+    info("iDOT", "multithreaded decoding hints", "Apple", AtMostOne, BeforeIdat),
+    info("CgBI", "byte-swapped, premultiplied data; not a standard PNG", "Apple", AtMostOne, BeforeIhdr),
+    info("vpAg", "virtual page size", "ImageMagick", AtMostOne, BeforeIdat),
+    info("caNv", "canvas size and offset", "ImageMagick", AtMostOne, BeforeIdat),
+    info("orNT", "orientation", "ImageMagick", AtMostOne, BeforeIdat),
+    info("mkBF", "editing data", "Adobe Fireworks", Any, Anywhere),
+    info("mkBS", "editing data", "Adobe Fireworks", Any, Anywhere),
+    info("mkBT", "editing data", "Adobe Fireworks", Any, Anywhere),
+    info("mkTS", "editing data", "Adobe Fireworks", Any, Anywhere),
+];
+
+/// Looks up a chunk type in the registry
+pub fn lookup(chunk_type: &ChunkType) -> Option<&'static ChunkInfo> {
+    let bytes = chunk_type.bytes();
+    REGISTRY.iter().find(|info| info.name.as_bytes() == bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_registry_names_are_valid_and_unique() {
+        for (i, info) in REGISTRY.iter().enumerate() {
+            let chunk_type = ChunkType::from_str(info.name).unwrap();
+            assert!(chunk_type.is_valid(), "{}", info.name);
+            assert!(REGISTRY[..i].iter().all(|other| other.name != info.name), "{}", info.name);
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let info = lookup(&ChunkType::IDAT).unwrap();
+        assert_eq!(info.multiplicity, OneOrMore);
+        assert_eq!(info.placement, ImageData);
+        assert_eq!(lookup(&ChunkType::from_str("acTL").unwrap()).unwrap().source, APNG);
+        assert!(lookup(&ChunkType::from_str("ruSt").unwrap()).is_none());
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use serde::Serialize;
+
+use crate::chunk::Chunk;
+use crate::pixels::{self, Image};
+use crate::png::Png;
+use crate::zip;
+
+/// Ancillary chunks bigger than this are unusual outside of IDAT
+pub const OVERSIZED_ANCILLARY: usize = 64 * 1024;
+
+/// Text with more bits of entropy per byte than this looks like encoded or encrypted data
+pub const TEXT_ENTROPY_LIMIT: f64 = 5.0;
+
+/// Texts shorter than this do not give a meaningful entropy estimate
+const MIN_TEXT_LENGTH: usize = 32;
+
+/// Compressed text is only inflated this far, so a small zTXt or iTXt chunk cannot expand
+/// into gigabytes
+pub const MAX_TEXT_LENGTH: usize = 1024 * 1024;
+
+/// A single suspicious property of a file together with how much it adds to the risk score
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub score: u32,
+    pub message: String,
+}
+
+/// Everything `scan` found out about one PNG
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Probability from the chi-square attack that the LSBs carry embedded data
+    pub chi_square: Option<f64>,
+    /// Fraction of samples the RS analysis estimates to have been modified
+    pub rs_estimate: Option<f64>,
+    /// Why the pixel analyses could not run, if they did not
+    pub pixel_error: Option<String>,
+}
+
+impl Report {
+    /// Sum of all findings, capped at 100
+    pub fn risk(&self) -> u32 {
+        self.findings.iter().map(|f| f.score).sum::<u32>().min(100)
+    }
+
+    pub fn level(&self) -> &'static str {
+        match self.risk() {
+            0..=24 => "low",
+            25..=59 => "medium",
+            _ => "high",
+        }
+    }
+
+    fn add(&mut self, score: u32, message: String) {
+        self.findings.push(Finding { score, message });
+    }
+}
+
+/// Runs every structural and statistical check on a PNG
+pub fn scan(png: &Png) -> Report {
+    let mut report = Report::default();
+    for chunk in png.chunks() {
+        check_chunk_type(&mut report, chunk);
+        check_size(&mut report, chunk);
+        check_text(&mut report, chunk);
+    }
+
+    if !png.trailer().is_empty() {
+        let kind = if zip::is_zip(png.trailer()) { "a zip archive" } else { "data" };
+        report.add(30, format!("{} bytes of {} after IEND", png.trailer().len(), kind));
+    }
+
+    match pixels::decode(png) {
+        Ok(image) => {
+            report.chi_square = chi_square(&image);
+            report.rs_estimate = rs_analysis(&image);
+        }
+        Err(err) => report.pixel_error = Some(err.to_string()),
+    }
+    if let Some(p) = report.chi_square.filter(|p| *p > 0.9) {
+        report.add(30, format!("chi-square attack on LSBs gives embedding probability {:.3}", p));
+    }
+    if let Some(rate) = report.rs_estimate.filter(|rate| *rate >= 0.05) {
+        report.add(
+            ((rate * 100.0) as u32).min(40),
+            format!("RS analysis estimates {:.1}% of samples were modified", rate * 100.0),
+        );
+    }
+    report
+}
+
+fn check_chunk_type(report: &mut Report, chunk: &Chunk) {
+    let chunk_type = chunk.chunk_type();
+    let name = chunk_type.to_string();
+    if chunk_type.info().is_some() {
+        return;
+    }
+    if !chunk_type.is_public() {
+        report.add(20, format!("private chunk `{}` ({} bytes)", name, chunk.length()));
+    } else {
+        report.add(20, format!("unregistered public chunk `{}` ({} bytes)", name, chunk.length()));
+    }
+    if chunk_type.is_critical() {
+        report.add(30, format!("unknown chunk `{}` is marked critical", name));
+    }
+}
+
+fn check_size(report: &mut Report, chunk: &Chunk) {
+    if !chunk.chunk_type().is_critical() && chunk.length() as usize > OVERSIZED_ANCILLARY {
+        report.add(
+            15,
+            format!("ancillary chunk `{}` is unusually large ({} bytes)", chunk.chunk_type(), chunk.length()),
+        );
+    }
+}
+
+fn check_text(report: &mut Report, chunk: &Chunk) {
+    let name = chunk.chunk_type().to_string();
+    let Some((keyword, text)) = text_chunk(chunk) else {
+        return;
+    };
+    if text.len() > MAX_TEXT_LENGTH {
+        report.add(
+            30,
+            format!("`{}` chunk `{}` inflates to more than {} bytes", name, keyword, MAX_TEXT_LENGTH),
+        );
+        return;
+    }
+    if text.len() < MIN_TEXT_LENGTH {
+        return;
+    }
+    let entropy = entropy(&text);
+    if entropy > TEXT_ENTROPY_LIMIT {
+        report.add(
+            20,
+            format!("`{}` chunk `{}` has high entropy text ({:.2} bits/byte)", name, keyword, entropy),
+        );
+    }
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    ZlibDecoder::new(data).take(MAX_TEXT_LENGTH as u64 + 1).read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+/// Splits a tEXt, zTXt or iTXt chunk into its keyword and (decompressed) text. Compressed
+/// text stops after `MAX_TEXT_LENGTH + 1` bytes, so anything longer than `MAX_TEXT_LENGTH`
+/// has been cut off.
+pub fn text_chunk(chunk: &Chunk) -> Option<(String, Vec<u8>)> {
+    let data = chunk.data();
+    let nul = data.iter().position(|b| *b == 0)?;
+    let keyword = String::from_utf8_lossy(&data[..nul]).to_string();
+    let rest = &data[nul + 1..];
+    let text = match chunk.chunk_type().to_string().as_str() {
+        "tEXt" => rest.to_vec(),
+        "zTXt" => inflate(rest.get(1..)?)?,
+        "iTXt" => {
+            let (compressed, rest) = (*rest.first()? == 1, rest.get(2..)?);
+            let language_end = rest.iter().position(|b| *b == 0)?;
+            let rest = &rest[language_end + 1..];
+            let translated_end = rest.iter().position(|b| *b == 0)?;
+            let rest = &rest[translated_end + 1..];
+            if compressed { inflate(rest)? } else { rest.to_vec() }
+        }
+        _ => return None,
+    };
+    Some((keyword, text))
+}
+
+/// Shannon entropy in bits per byte
+pub fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    for byte in bytes {
+        *counts.entry(*byte).or_default() += 1;
+    }
+    let total = bytes.len() as f64;
+    counts
+        .values()
+        .map(|count| {
+            let p = *count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Samples worth analysing: colour channels (or palette indices) of images with at most 8 bits per sample
+fn analysable_samples(image: &Image) -> Option<Vec<u16>> {
+    if image.header.bit_depth > 8 {
+        return None;
+    }
+    let channels = image.channels();
+    let colour = if image.header.has_alpha() { channels - 1 } else { channels };
+    Some(
+        image
+            .samples
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % channels < colour)
+            .map(|(_, s)| *s)
+            .collect(),
+    )
+}
+
+/// Westfeld and Pfitzmann's chi-square attack. Embedding random bits into the LSBs
+/// evens out the counts of each pair of values 2k and 2k+1; the returned value is the
+/// probability that the observed counts are that even because of embedding.
+pub fn chi_square(image: &Image) -> Option<f64> {
+    let samples = analysable_samples(image)?;
+    let mut histogram = [0usize; 256];
+    for sample in samples {
+        histogram[sample as usize] += 1;
+    }
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        // the approximation is only valid for reasonably populated categories
+        if expected < 5.0 {
+            continue;
+        }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        categories += 1;
+    }
+    if categories < 2 {
+        return None;
+    }
+    Some(gamma_q((categories - 1) as f64 / 2.0, statistic / 2.0))
+}
+
+/// Sum of absolute differences between neighbours, the "smoothness" of a group
+fn discrimination(group: &[i32]) -> i32 {
+    group.windows(2).map(|w| (w[1] - w[0]).abs()).sum()
+}
+
+const RS_MASK: [bool; 4] = [false, true, true, false];
+
+/// Counts of regular and singular groups under the mask and the negated mask
+fn rs_counts(groups: &[[i32; 4]]) -> (f64, f64, f64, f64) {
+    let (mut r, mut s, mut nr, mut ns) = (0, 0, 0, 0);
+    for group in groups {
+        let original = discrimination(group);
+        let mut flipped = *group;
+        let mut negated = *group;
+        for i in 0..4 {
+            if RS_MASK[i] {
+                flipped[i] ^= 1;
+                negated[i] = ((negated[i] + 1) ^ 1) - 1;
+            }
+        }
+        match discrimination(&flipped).cmp(&original) {
+            std::cmp::Ordering::Greater => r += 1,
+            std::cmp::Ordering::Less => s += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+        match discrimination(&negated).cmp(&original) {
+            std::cmp::Ordering::Greater => nr += 1,
+            std::cmp::Ordering::Less => ns += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    let n = groups.len() as f64;
+    (r as f64 / n, s as f64 / n, nr as f64 / n, ns as f64 / n)
+}
+
+/// Fridrich's RS steganalysis: estimates the fraction of samples whose LSB was
+/// overwritten, from how flipping LSBs changes the smoothness of small pixel groups.
+pub fn rs_analysis(image: &Image) -> Option<f64> {
+    if image.header.bit_depth != 8 || image.header.color_type == 3 {
+        return None;
+    }
+    let channels = image.channels();
+    let colour = if image.header.has_alpha() { channels - 1 } else { channels };
+    let mut groups = vec![];
+    for row in 0..image.height() {
+        for channel in 0..colour {
+            let line: Vec<i32> = image.row_channel(row, channel).map(|s| s as i32).collect();
+            groups.extend(line.chunks_exact(4).map(|g| [g[0], g[1], g[2], g[3]]));
+        }
+    }
+    if groups.len() < 64 {
+        return None;
+    }
+    let flipped: Vec<[i32; 4]> = groups.iter().map(|g| g.map(|s| s ^ 1)).collect();
+
+    let (r, s, nr, ns) = rs_counts(&groups);
+    let (r1, s1, nr1, ns1) = rs_counts(&flipped);
+    let (d0, d1, dn0, dn1) = (r - s, r1 - s1, nr - ns, nr1 - ns1);
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let x = if a.abs() < f64::EPSILON {
+        if b.abs() < f64::EPSILON {
+            return None;
+        }
+        -c / b
+    } else {
+        let disc = b * b - 4.0 * a * c;
+        if disc < 0.0 {
+            return None;
+        }
+        let roots = [(-b + disc.sqrt()) / (2.0 * a), (-b - disc.sqrt()) / (2.0 * a)];
+        if roots[0].abs() < roots[1].abs() { roots[0] } else { roots[1] }
+    };
+    let rate = x / (x - 0.5);
+    Some(if rate.is_finite() { rate.clamp(0.0, 1.0) } else { 0.0 })
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let mut y = x;
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for c in COEFFICIENTS {
+        y += 1.0;
+        series += c / y;
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Upper regularized incomplete gamma function Q(a, x), the survival function of chi-square
+fn gamma_q(a: f64, x: f64) -> f64 {
+    const EPS: f64 = 1e-12;
+    const TINY: f64 = 1e-300;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut ap, mut delta) = (a, 1.0 / a);
+        let mut sum = delta;
+        for _ in 0..1000 {
+            ap += 1.0;
+            delta *= x / ap;
+            sum += delta;
+            if delta.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        (1.0 - sum * prefix).clamp(0.0, 1.0)
+    } else {
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPS {
+                break;
+            }
+        }
+        (prefix * h).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::Header;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    /// Deterministic pseudo random numbers so the statistical tests are repeatable
+    fn lcg(seed: &mut u64) -> u32 {
+        *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (*seed >> 33) as u32
+    }
+
+    /// A smooth grey image with a little noise, like a photo of a flat surface
+    fn smooth_image(random_lsbs: bool) -> Image {
+        let (width, height) = (128, 128);
+        let mut seed = 7;
+        let mut samples = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let base = 40 + (x + y) / 2 + (lcg(&mut seed) % 3) as usize;
+                let sample = if random_lsbs { (base & !1) | (lcg(&mut seed) & 1) as usize } else { base };
+                samples.push(sample as u16);
+            }
+        }
+        let header = Header { width: width as u32, height: height as u32, bit_depth: 8, color_type: 0, interlaced: false };
+        Image { header, samples }
+    }
+
+    #[test]
+    fn test_gamma_q_known_values() {
+        // chi-square with 2 degrees of freedom has survival function exp(-x/2)
+        assert!((gamma_q(1.0, 1.5) - (-1.5f64).exp()).abs() < 1e-9);
+        assert!((gamma_q(1.0, 0.2) - (-0.2f64).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b"aaaaaaaa"), 0.0);
+        assert!((entropy(b"abcdabcd") - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_chi_square_detects_even_pairs() {
+        let header = Header { width: 256, height: 8, bit_depth: 8, color_type: 0, interlaced: false };
+        // only even values: clearly no LSB embedding
+        let clean = Image { header, samples: (0..2048).map(|i| (i % 128 * 2) as u16).collect() };
+        // every pair equally populated: what full LSB embedding produces
+        let embedded = Image { header, samples: (0..2048).map(|i| (i % 256) as u16).collect() };
+
+        assert!(chi_square(&clean).unwrap() < 0.01);
+        assert!(chi_square(&embedded).unwrap() > 0.99);
+    }
+
+    #[test]
+    fn test_rs_analysis_detects_random_lsbs() {
+        let clean = rs_analysis(&smooth_image(false)).unwrap();
+        let embedded = rs_analysis(&smooth_image(true)).unwrap();
+
+        assert!(embedded > clean);
+        assert!(embedded > 0.5);
+    }
+
+    #[test]
+    fn test_text_chunk_keyword() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0hello".to_vec());
+        let (keyword, text) = text_chunk(&chunk).unwrap();
+        assert_eq!(keyword, "Comment");
+        assert_eq!(text, b"hello");
+    }
+
+    #[test]
+    fn test_scan_flags_text_bomb() {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::best());
+        encoder.write_all(&vec![b'a'; 4 * MAX_TEXT_LENGTH]).unwrap();
+        let data: Vec<u8> = b"Comment\0\0".iter().copied().chain(encoder.finish().unwrap()).collect();
+        let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), data);
+        assert!(chunk.data().len() < 64 * 1024);
+
+        let (_, text) = text_chunk(&chunk).unwrap();
+        assert_eq!(text.len(), MAX_TEXT_LENGTH + 1);
+
+        let mut png = Png::try_from(DICE).unwrap();
+        png.append_chunk(chunk);
+        let report = scan(&png);
+        assert!(report.findings.iter().any(|f| f.message.contains("inflates to more than")));
+    }
+
+    #[test]
+    fn test_scan_clean_file() {
+        let png = Png::try_from(DICE).unwrap();
+        let report = scan(&png);
+
+        assert!(report.pixel_error.is_none());
+        assert!(report.chi_square.is_some());
+        assert_eq!(report.level(), "low");
+    }
+
+    #[test]
+    fn test_scan_flags_private_chunk_and_trailer() {
+        let mut png = Png::try_from(DICE).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"secret".to_vec()));
+        png.set_trailer(b"more secrets".to_vec());
+        let report = scan(&png);
+
+        assert!(report.findings.iter().any(|f| f.message.contains("private chunk `ruSt`")));
+        assert!(report.findings.iter().any(|f| f.message.contains("after IEND")));
+        assert!(report.risk() >= 50);
+    }
+
+    #[test]
+    fn test_scan_flags_high_entropy_text() {
+        let mut png = Png::try_from(DICE).unwrap();
+        let mut seed = 1;
+        let noise: Vec<u8> = (0..200).map(|_| b'!' + (lcg(&mut seed) % 90) as u8).collect();
+        let data: Vec<u8> = b"Comment\0".iter().chain(noise.iter()).copied().collect();
+        png.append_chunk(Chunk::new(ChunkType::from_str("tEXt").unwrap(), data));
+        let report = scan(&png);
+
+        assert!(report.findings.iter().any(|f| f.message.contains("high entropy")));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Ancillary chunks that affect how colors are displayed
+pub const COLOR_MANAGEMENT_TYPES: [&str; 4] = ["gAMA", "cHRM", "sRGB", "iCCP"];
+
+/// Chunks every policy keeps: critical chunks, without which the image cannot be decoded,
+/// and tRNS, without which transparent pixels turn opaque
+pub fn is_essential(chunk_type: &ChunkType) -> bool {
+    chunk_type.is_critical() || *chunk_type == ChunkType::TRNS
+}
+
+/// Which chunks survive stripping, on top of the essential ones
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Policy {
+    /// Keep nothing but essential chunks
+    Critical,
+    /// Keep essential and color management chunks
+    Color,
+    /// Keep essential chunks and the listed types
+    Allow(Vec<ChunkType>),
+    /// Keep everything except the listed types and unregistered chunks that are not safe to
+    /// copy, whose data depends on the image in ways nothing here can check
+    Deny(Vec<ChunkType>),
+}
+
+impl Policy {
+    pub fn keeps(&self, chunk_type: &ChunkType) -> bool {
+        if is_essential(chunk_type) {
+            return true;
+        }
+        match self {
+            Policy::Critical => false,
+            Policy::Color => COLOR_MANAGEMENT_TYPES.contains(&chunk_type.to_string().as_str()),
+            Policy::Allow(types) => types.contains(chunk_type),
+            Policy::Deny(types) => {
+                !types.contains(chunk_type) && (chunk_type.is_safe_to_copy() || chunk_type.info().is_some())
+            }
+        }
+    }
+}
+
+/// A chunk that was dropped while stripping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removed {
+    pub chunk_type: ChunkType,
+    pub length: u32,
+}
+
+impl Removed {
+    /// Bytes the chunk took up in the file, including its length, type and CRC fields
+    pub fn size(&self) -> usize {
+        self.length as usize + 12
+    }
+}
+
+/// What `strip` removed from a PNG
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub removed: Vec<Removed>,
+    pub trailer: usize,
+}
+
+impl Report {
+    pub fn bytes_saved(&self) -> usize {
+        self.removed.iter().map(Removed::size).sum::<usize>() + self.trailer
+    }
+}
+
+/// Removes every chunk the policy does not keep, along with any data after IEND
+pub fn strip(png: &mut Png, policy: &Policy) -> Report {
+    let mut report = Report { removed: vec![], trailer: png.trailer().len() };
+    png.retain(|chunk| {
+        let keep = policy.keeps(chunk.chunk_type());
+        if !keep {
+            report.removed.push(Removed { chunk_type: chunk.chunk_type().clone(), length: chunk.length() });
+        }
+        keep
+    });
+    png.set_trailer(vec![]);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_strip_critical() {
+        let mut png = Png::try_from(DICE).unwrap();
+        png.set_trailer(b"extra".to_vec());
+        let before = png.as_bytes().len();
+        let report = strip(&mut png, &Policy::Critical);
+
+        assert_eq!(types(&png), vec!["IHDR", "IDAT", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(report.removed.len(), 6);
+        assert_eq!(report.trailer, 5);
+        assert_eq!(report.bytes_saved(), before - png.as_bytes().len());
+    }
+
+    #[test]
+    fn test_strip_color_keeps_color_management() {
+        let mut png = Png::try_from(DICE).unwrap();
+        strip(&mut png, &Policy::Color);
+        assert_eq!(types(&png), vec!["IHDR", "gAMA", "cHRM", "IDAT", "IDAT", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_strip_allow_and_deny() {
+        let text = ChunkType::from_str("tEXt").unwrap();
+
+        let mut png = Png::try_from(DICE).unwrap();
+        strip(&mut png, &Policy::Allow(vec![text.clone()]));
+        assert_eq!(types(&png), vec!["IHDR", "IDAT", "IDAT", "IDAT", "tEXt", "tEXt", "IEND"]);
+
+        let mut png = Png::try_from(DICE).unwrap();
+        let report = strip(&mut png, &Policy::Deny(vec![text]));
+        assert_eq!(report.removed.len(), 2);
+        assert!(!types(&png).contains(&"tEXt".to_string()));
+    }
+
+    #[test]
+    fn test_keeps_trns() {
+        let trns = ChunkType::from_str("tRNS").unwrap();
+        assert!(Policy::Critical.keeps(&trns));
+        assert!(Policy::Deny(vec![trns.clone()]).keeps(&trns));
+    }
+
+    #[test]
+    fn test_deny_drops_unknown_unsafe_to_copy_chunks() {
+        let deny = Policy::Deny(vec![]);
+        assert!(deny.keeps(&ChunkType::from_str("ruSt").unwrap()));
+        assert!(!deny.keeps(&ChunkType::from_str("ruST").unwrap()));
+        assert!(deny.keeps(&ChunkType::from_str("gAMA").unwrap()));
+    }
+
+    #[test]
+    fn test_deny_never_removes_critical() {
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        assert!(Policy::Deny(vec![idat.clone()]).keeps(&idat));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{Error, Result};
+
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const CENTRAL_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+const EOCD_LENGTH: usize = 22;
+const CENTRAL_HEADER_LENGTH: usize = 46;
+
+fn read_u16(bytes: &[u8], at: usize) -> usize {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn write_u32(bytes: &mut [u8], at: usize, value: u32) {
+    bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Finds the end of central directory record, scanning backwards past any archive comment
+fn find_eocd(zip: &[u8]) -> Option<usize> {
+    if zip.len() < EOCD_LENGTH {
+        return None;
+    }
+    (0..=zip.len() - EOCD_LENGTH).rev().find(|&i| {
+        zip[i..i + 4] == EOCD_SIGNATURE && i + EOCD_LENGTH + read_u16(zip, i + 20) == zip.len()
+    })
+}
+
+/// Returns true if the bytes end with a zip end of central directory record
+pub fn is_zip(bytes: &[u8]) -> bool {
+    find_eocd(bytes).is_some()
+}
+
+/// Rewrites the offsets stored in a zip archive so that they are correct once
+/// `base` bytes are placed in front of it. A base of 0 turns an archive cut out
+/// of a larger file back into a standalone one.
+pub fn rebase(zip: &[u8], base: u32) -> Result<Vec<u8>> {
+    let eocd = find_eocd(zip).ok_or_else(|| Error::from("no end of central directory record found"))?;
+    let entries = read_u16(zip, eocd + 10);
+    let cd_size = read_u32(zip, eocd + 12);
+    let stored_cd_offset = read_u32(zip, eocd + 16);
+    if cd_size == u32::MAX || stored_cd_offset == u32::MAX || entries == u16::MAX as usize {
+        return Err(Error::from("zip64 archives are not supported"));
+    }
+    let cd_start = eocd
+        .checked_sub(cd_size as usize)
+        .ok_or_else(|| Error::from("central directory size is larger than the archive"))?;
+    // difference between where the archive currently thinks it starts and where we want it
+    let shift = base as i64 + cd_start as i64 - stored_cd_offset as i64;
+    let relocate = |offset: u32| -> Result<u32> {
+        u32::try_from(offset as i64 + shift)
+            .map_err(|_| Error::from("archive offsets do not fit after relocation"))
+    };
+
+    let mut out = zip.to_vec();
+    let mut i = cd_start;
+    for _ in 0..entries {
+        if i + CENTRAL_HEADER_LENGTH > eocd || zip[i..i + 4] != CENTRAL_HEADER_SIGNATURE {
+            return Err(Error::from(format!("bad central directory entry at offset {}", i)));
+        }
+        let local_offset = read_u32(zip, i + 42);
+        if local_offset == u32::MAX {
+            return Err(Error::from("zip64 archives are not supported"));
+        }
+        write_u32(&mut out, i + 42, relocate(local_offset)?);
+        i += CENTRAL_HEADER_LENGTH + read_u16(zip, i + 28) + read_u16(zip, i + 30) + read_u16(zip, i + 32);
+    }
+    write_u32(&mut out, eocd + 16, relocate(stored_cd_offset)?);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A stored (uncompressed) archive holding `a.txt` with the contents "hi\n"
+    fn testing_zip() -> Vec<u8> {
+        let name = b"a.txt";
+        let data = b"hi\n";
+        let crc: u32 = 0xed6f_7a7a;
+        let mut zip = vec![];
+        zip.extend(0x0403_4b50u32.to_le_bytes());
+        zip.extend([20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        zip.extend(crc.to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend((name.len() as u16).to_le_bytes());
+        zip.extend([0, 0]);
+        zip.extend(name);
+        zip.extend(data);
+
+        let cd_start = zip.len() as u32;
+        zip.extend(CENTRAL_HEADER_SIGNATURE);
+        zip.extend([20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        zip.extend(crc.to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend((name.len() as u16).to_le_bytes());
+        zip.extend([0; 12]);
+        zip.extend(0u32.to_le_bytes());
+        zip.extend(name);
+        let cd_size = zip.len() as u32 - cd_start;
+
+        zip.extend(EOCD_SIGNATURE);
+        zip.extend([0, 0, 0, 0, 1, 0, 1, 0]);
+        zip.extend(cd_size.to_le_bytes());
+        zip.extend(cd_start.to_le_bytes());
+        zip.extend([0, 0]);
+        zip
+    }
+
+    #[test]
+    fn test_is_zip() {
+        assert!(is_zip(&testing_zip()));
+        assert!(!is_zip(b"not a zip archive at all"));
+    }
+
+    #[test]
+    fn test_rebase_shifts_offsets() {
+        let zip = testing_zip();
+        let moved = rebase(&zip, 100).unwrap();
+        let eocd = find_eocd(&moved).unwrap();
+        let cd_start = eocd - read_u32(&moved, eocd + 12) as usize;
+
+        assert_eq!(read_u32(&moved, eocd + 16) as usize, cd_start + 100);
+        assert_eq!(read_u32(&moved, cd_start + 42), 100);
+    }
+
+    #[test]
+    fn test_rebase_round_trip() {
+        let zip = testing_zip();
+        let moved = rebase(&zip, 4803).unwrap();
+        assert_ne!(moved, zip);
+        assert_eq!(rebase(&moved, 0).unwrap(), zip);
+    }
+
+    #[test]
+    fn test_rebase_rejects_garbage() {
+        assert!(rebase(b"PK but not really", 0).is_err());
+    }
+}